@@ -0,0 +1,84 @@
+//! Command-line evaluation of tutor prompt template versions.
+//!
+//! Replays a suite of golden cases with a baseline and a candidate version of
+//! the tutor system prompt and reports the cases that regressed or improved.
+//! Exits with an error if any case regressed.
+//!
+//! Replies come from recorded responses, or from the model with `--live`, which
+//! reads the API key from `ANTHROPIC_API_KEY`. Live replies go through the
+//! fixture layer: set `ANTHROPIC_FIXTURES=record:<dir>` to save them and
+//! `replay:<dir>` to run the suite again without calling the model.
+//!
+//! ```sh
+//! cargo run --bin eval-prompts -- evals/tutor_suite.json evals/recordings.json 1 2
+//! ANTHROPIC_FIXTURES=record:fixtures/anthropic cargo run --bin eval-prompts -- --live evals/tutor_suite.json 1 3
+//! ```
+
+use std::process::ExitCode;
+use serde::de::DeserializeOwned;
+use devops_ai_api::eval::{self, CompletionProvider, EvalReport, EvalSuite, FixtureMode, LiveProvider, RecordedProvider, RecordedResponse};
+
+const USAGE: &str = "usage: eval-prompts <suite.json> <recordings.json> <baseline version> <candidate version>
+       eval-prompts --live <suite.json> <baseline version> <candidate version>";
+
+fn main() -> ExitCode {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let live = args.first().map(String::as_str) == Some("--live");
+    if live {
+        args.remove(0);
+    }
+    if args.len() != if live { 3 } else { 4 } {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    }
+
+    let suite: EvalSuite = match read_json(&args[0]) {
+        Ok(suite) => suite,
+        Err(e) => return fail(&e),
+    };
+    let mut provider: Box<dyn CompletionProvider> = if live {
+        let api_key = std::env::var("ANTHROPIC_API_KEY").unwrap_or_default();
+        match LiveProvider::new(api_key, FixtureMode::from_env()) {
+            Ok(provider) => Box::new(provider),
+            Err(e) => return fail(&e),
+        }
+    } else {
+        match read_json::<Vec<RecordedResponse>>(&args[1]) {
+            Ok(recordings) => Box::new(RecordedProvider::new(recordings)),
+            Err(e) => return fail(&e),
+        }
+    };
+    let versions = &args[args.len() - 2..];
+    let (baseline, candidate) = match (versions[0].parse::<u32>(), versions[1].parse::<u32>()) {
+        (Ok(baseline), Ok(candidate)) => (baseline, candidate),
+        _ => return fail("versions must be numbers"),
+    };
+
+    let runs = eval::run_suite(&suite, baseline, provider.as_mut())
+        .and_then(|baseline| Ok((baseline, eval::run_suite(&suite, candidate, provider.as_mut())?)));
+    let report = match runs {
+        Ok((baseline, candidate)) => EvalReport::compare(baseline, candidate),
+        Err(e) => return fail(&e),
+    };
+
+    print!("{}", report);
+    if report.regressions().is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+/// Reads and parses a JSON file.
+fn read_json<T: DeserializeOwned>(path: &str) -> Result<T, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| format!("{}: failed to read file: {}", path, e))?;
+    serde_json::from_str(&contents)
+        .map_err(|e| format!("{}: invalid JSON: {}", path, e))
+}
+
+/// Prints an error and returns a failing exit code.
+fn fail(message: &str) -> ExitCode {
+    eprintln!("{}", message);
+    ExitCode::FAILURE
+}
@@ -0,0 +1,48 @@
+//! Command-line linter for topic documents.
+//!
+//! Validates the built-in topic catalogue together with any topic JSON files
+//! given as arguments. Each file may contain a single topic or an array of topics.
+//!
+//! ```sh
+//! cargo run --bin lint-topics -- path/to/topic.json
+//! ```
+
+use std::process::ExitCode;
+use devops_ai_api::topics;
+use devops_ai_api::types::Topic;
+use devops_ai_api::validation;
+
+fn main() -> ExitCode {
+    let mut catalogue = topics::get_all_topics();
+
+    for path in std::env::args().skip(1) {
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                eprintln!("{}: failed to read file: {}", path, e);
+                return ExitCode::FAILURE;
+            }
+        };
+
+        let parsed = serde_json::from_str::<Vec<Topic>>(&contents)
+            .or_else(|_| serde_json::from_str::<Topic>(&contents).map(|topic| vec![topic]));
+        match parsed {
+            Ok(topics) => catalogue.extend(topics),
+            Err(e) => {
+                eprintln!("{}: invalid topic document: {}", path, e);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    let reports = validation::validate_catalogue(&catalogue);
+    if reports.is_empty() {
+        println!("{} topic(s) OK", catalogue.len());
+        return ExitCode::SUCCESS;
+    }
+
+    for report in &reports {
+        eprint!("{}", report);
+    }
+    ExitCode::FAILURE
+}
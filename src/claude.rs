@@ -2,7 +2,49 @@
 
 use worker::*;
 use reqwest::Client;
-use crate::types::{TimestampedChatMessage, ClaudeRequest, ClaudeResponse, ClaudeMessage};
+use crate::types::{Attachment, TimestampedChatMessage, ClaudeRequest, ClaudeResponse, ClaudeMessage, ClaudeContentBlock, ClaudeTool, ClaudeToolChoice, ClaudeUsage, GuardrailPolicy};
+use crate::attachments;
+use crate::fixtures::{self, FixtureMode};
+use crate::framing;
+use crate::grading;
+use crate::tools::ToolExecutor;
+
+/// The Claude Messages API endpoint.
+const API_URL: &str = "https://api.anthropic.com/v1/messages";
+/// The model used for all requests.
+const MODEL: &str = "claude-3-5-sonnet-20240620";
+/// Maximum number of API calls made for one reply when the model calls tools.
+const MAX_TOOL_ROUNDS: u32 = 4;
+
+/// Represents how requests reach the Claude Messages API: the API key, and
+/// whether responses are recorded or replayed from fixtures.
+#[derive(Debug, Clone)]
+pub struct ClaudeClient {
+    /// The API key for authentication with the Claude API
+    pub api_key: String,
+    /// How calls are handled
+    pub fixtures: FixtureMode,
+}
+
+impl ClaudeClient {
+    /// Creates a client calling the API with the given key, as workers do.
+    pub fn live(api_key: String) -> Self {
+        ClaudeClient { api_key, fixtures: FixtureMode::Live }
+    }
+}
+
+/// Represents a reply from Claude, with the tokens used to generate it.
+#[derive(Debug)]
+pub struct ClaudeReply {
+    /// The text blocks of the reply, joined
+    pub text: String,
+    /// Usage statistics for the API calls, summed over tool-use rounds
+    pub usage: ClaudeUsage,
+    /// Number of API calls made, one per tool-use round
+    pub requests: u32,
+    /// The names of the tools called while generating the reply, in order
+    pub tool_calls: Vec<String>,
+}
 
 /// Formats a conversation for sending to the Claude API.
 ///
@@ -18,7 +60,7 @@ pub fn format_conversation(conversation: Vec<(&str, &str)>) -> Vec<ClaudeMessage
     conversation.into_iter()
         .map(|(role, content)| ClaudeMessage {
             role: role.to_string(),
-            content: content.to_string(),
+            content: vec![ClaudeContentBlock::text(content)],
             name: None,
         })
         .collect()
@@ -27,6 +69,7 @@ pub fn format_conversation(conversation: Vec<(&str, &str)>) -> Vec<ClaudeMessage
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::guardrails::default_policy;
 
     #[test]
     fn test_format_conversation() {
@@ -40,82 +83,530 @@ mod tests {
 
         assert_eq!(formatted.len(), 3);
         assert_eq!(formatted[0].role, "user");
-        assert_eq!(formatted[0].content, "Hello, Claude!");
+        assert_eq!(formatted[0].text(), "Hello, Claude!");
         assert_eq!(formatted[1].role, "assistant");
-        assert_eq!(formatted[1].content, "Hello! How can I assist you today?");
+        assert_eq!(formatted[1].text(), "Hello! How can I assist you today?");
         assert_eq!(formatted[2].role, "user");
-        assert_eq!(formatted[2].content, "Tell me about Rust programming.");
+        assert_eq!(formatted[2].text(), "Tell me about Rust programming.");
+    }
+
+    /// Runs a future to completion. Replayed API calls never wait, so polling in a loop is enough.
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+        fn raw_waker() -> RawWaker {
+            fn clone(_: *const ()) -> RawWaker { raw_waker() }
+            fn noop(_: *const ()) {}
+            static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+            RawWaker::new(std::ptr::null(), &VTABLE)
+        }
+
+        let waker = unsafe { Waker::from_raw(raw_waker()) };
+        let mut context = Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+                return output;
+            }
+        }
+    }
+
+    /// Returns a client serving API responses from the fixtures recorded in the repository.
+    fn replay_client() -> ClaudeClient {
+        ClaudeClient {
+            api_key: "test-key".to_string(),
+            fixtures: FixtureMode::Replay(std::path::PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/anthropic"))),
+        }
+    }
+
+    /// Returns the progress of a learner who completed every step before the given one.
+    fn learner_progress(topic: &crate::types::Topic, step_id: &str) -> crate::types::Progress {
+        let step_index = topic.steps.iter().position(|step| step.id == step_id).unwrap();
+        let mut progress = crate::types::Progress::new(&topic.id, topic.version);
+        progress.completed_steps = (0..step_index).collect();
+        progress.current_step = step_index;
+        progress
+    }
+
+    /// Builds the conversation, tutor system prompt and retrieved context of a learner asking a question on a step.
+    fn chat_request(step_id: &str, history: &[(&str, &str)], question: &str) -> (Vec<TimestampedChatMessage>, String, String) {
+        let topic = crate::topics::get_github_setup_topic();
+        let progress = learner_progress(&topic, step_id);
+        let step_index = progress.current_step;
+
+        let conversation: Vec<TimestampedChatMessage> = history.iter()
+            .chain(std::iter::once(&("user", question)))
+            .enumerate()
+            .map(|(position, (role, content))| TimestampedChatMessage {
+                id: format!("m{}", position + 1),
+                role: role.to_string(),
+                content: content.to_string(),
+                timestamp: chrono::Utc::now(),
+                step_id: Some(step_id.to_string()),
+                attachments: vec![],
+                alternates: vec![],
+                prompt_version: None,
+            })
+            .collect();
+
+        let documents = crate::rag::retrieve(&crate::rag::build_index(&crate::rag::builtin_documents()), question, crate::rag::RETRIEVAL_LIMIT);
+        let variables = crate::prompts::tutor_variables(
+            &topic,
+            topic.steps.get(step_index),
+            &progress,
+            crate::i18n::DEFAULT_LOCALE,
+            framing::framing_instruction(),
+            crate::followups::follow_up_instruction(),
+        );
+        let template = crate::prompts::find_template(crate::prompts::TUTOR_SYSTEM, crate::prompts::DEFAULT_VERSION).unwrap();
+        (conversation, crate::prompts::render(&template.body, &variables).unwrap(), crate::rag::context_instruction(&documents))
+    }
+
+    /// Asks for a tutor reply with every tool available, running the tools for a learner on the given step.
+    ///
+    /// Returns the reply and the learner's progress if a tool changed it.
+    fn tutor_reply(step_id: &str, conversation: &[TimestampedChatMessage], system_prompt: &str, context: &str) -> (Result<ClaudeReply>, Option<crate::types::Progress>) {
+        let topic = crate::topics::get_github_setup_topic();
+        let index = crate::rag::build_index(&crate::rag::builtin_documents());
+        let mut session = crate::tools::ToolSession::new(&topic, &index, learner_progress(&topic, step_id));
+
+        let tools = crate::tools::allowed_tools(&topic);
+        let reply = block_on(call_claude_api_with_history(conversation, &replay_client(), system_prompt, context, &default_policy(), &tools, &mut session));
+        (reply, if session.progress_changed { Some(session.progress) } else { None })
+    }
+
+    #[test]
+    fn test_chat_replays_fixture() {
+        let (conversation, system_prompt, context) = chat_request(
+            "commit-changes",
+            &[("user", "I edited README.md. What now?"), ("assistant", "Check what changed with `git status`, then stage the file.")],
+            "How do I stage and commit it?",
+        );
+
+        let (reply, progress) = tutor_reply("commit-changes", &conversation, &system_prompt, &context);
+        let reply = reply.unwrap();
+        let (response, follow_ups) = crate::followups::extract_follow_ups(&reply.text);
+
+        assert!(response.contains("git add README.md"));
+        assert_eq!(follow_ups, vec!["How do I write a good commit message?"]);
+        assert_eq!(reply.usage.cache_read_input_tokens, Some(1778));
+        assert_eq!(reply.requests, 1);
+        assert!(reply.tool_calls.is_empty());
+        assert!(progress.is_none());
+    }
+
+    #[test]
+    fn test_tool_use_replays_fixtures() {
+        let (conversation, system_prompt, context) = chat_request(
+            "commit-changes",
+            &[("user", "How do I commit README.md?"), ("assistant", "Run `git add README.md`, then `git commit -m \"Update README\"`.")],
+            "Done, git log shows my commit!",
+        );
+
+        let (reply, progress) = tutor_reply("commit-changes", &conversation, &system_prompt, &context);
+        let reply = reply.unwrap();
+
+        assert_eq!(reply.tool_calls, vec![crate::tools::MARK_STEP_COMPLETE]);
+        assert!(reply.text.contains("Push your changes"));
+        assert_eq!(reply.usage.output_tokens, 56 + 71);
+        assert_eq!(reply.requests, 2);
+        assert_eq!(progress.unwrap().completed_step_ids.last().map(String::as_str), Some("commit-changes"));
+    }
+
+    #[test]
+    fn test_response_content_blocks() {
+        let response: ClaudeResponse = serde_json::from_value(serde_json::json!({
+            "id": "msg_01",
+            "type": "message",
+            "role": "assistant",
+            "model": MODEL,
+            "content": [
+                {"type": "text", "text": "Let me look up that step."},
+                {"type": "tool_use", "id": "toolu_01", "name": "get_step", "input": {"step_id": "ssh-keys"}},
+                {"type": "text", "text": "One moment."},
+                {"type": "thinking", "thinking": "The learner is on the SSH step.", "signature": "c2ln"}
+            ],
+            "stop_reason": "tool_use",
+            "stop_sequence": null,
+            "usage": {"input_tokens": 10, "output_tokens": 20}
+        })).unwrap();
+
+        assert_eq!(response.text(), "Let me look up that step.\n\nOne moment.");
+        assert_eq!(response.content[1], ClaudeContentBlock::ToolUse {
+            id: "toolu_01".to_string(),
+            name: "get_step".to_string(),
+            input: serde_json::json!({"step_id": "ssh-keys"}),
+        });
+        assert_eq!(response.content[3], ClaudeContentBlock::Unknown);
+
+        let result = ClaudeContentBlock::ToolResult {
+            tool_use_id: "toolu_01".to_string(),
+            content: vec![ClaudeContentBlock::text("Generate an SSH key.")],
+            is_error: None,
+        };
+        assert_eq!(serde_json::to_value(&result).unwrap(), serde_json::json!({
+            "type": "tool_result",
+            "tool_use_id": "toolu_01",
+            "content": [{"type": "text", "text": "Generate an SSH key."}]
+        }));
+        assert_eq!(serde_json::to_value(ClaudeContentBlock::image("image/png", "iVBORw0KGgo=")).unwrap(), serde_json::json!({
+            "type": "image",
+            "source": {"type": "base64", "media_type": "image/png", "data": "iVBORw0KGgo="}
+        }));
+    }
+
+    #[test]
+    fn test_build_messages_forwards_attachments() {
+        let (mut conversation, _, _) = chat_request("commit-changes", &[("user", "Is this the right remote?"), ("assistant", "Yes.")], "Why does the push fail?");
+        conversation[0].attachments = vec![
+            Attachment { name: "remote.png".to_string(), media_type: "image/png".to_string(), data: String::new(), size: 8 },
+        ];
+        conversation[2].attachments = vec![
+            Attachment { name: "error.png".to_string(), media_type: "image/png".to_string(), data: "iVBORw0KGgo=".to_string(), size: 8 },
+            Attachment { name: "push.log".to_string(), media_type: "text/plain".to_string(), data: "! [rejected] main -> main (fetch first)\n".to_string(), size: 39 },
+        ];
+
+        let messages = build_messages(&conversation, "", &default_policy());
+
+        assert_eq!(messages[0].content[0].as_text(), Some("<attached_file name=\"remote.png\">\n(attached earlier; the contents are no longer shown)\n</attached_file>"));
+        assert_eq!(messages[2].content.len(), 3);
+        assert_eq!(messages[2].content[0], ClaudeContentBlock::image("image/png", "iVBORw0KGgo="));
+        assert_eq!(messages[2].content[1].as_text(), Some("<attached_file name=\"push.log\">\n! [rejected] main -> main (fetch first)\n</attached_file>"));
+        assert!(messages[2].content[2].as_text().unwrap().starts_with("<learner_message>"));
+    }
+
+    #[test]
+    fn test_build_messages_marks_cache_breakpoint() {
+        let (conversation, _, _) = chat_request(
+            "commit-changes",
+            &[("user", "I edited README.md. What now?"), ("assistant", "Stage the file.")],
+            "How do I commit it?",
+        );
+
+        let messages = build_messages(&conversation, "CONTEXT", &default_policy());
+
+        let breakpoints: Vec<(usize, usize)> = messages.iter().enumerate()
+            .flat_map(|(index, message)| message.content.iter().enumerate()
+                .filter(|(_, block)| block.cache_control().is_some())
+                .map(move |(block, _)| (index, block)))
+            .collect();
+        assert_eq!(breakpoints, vec![(1, 0)]);
+        assert_eq!(messages[2].content.len(), 2);
+        assert_eq!(messages[2].content[0].as_text(), Some("CONTEXT"));
+        assert!(messages[2].content[1].as_text().unwrap().starts_with("<learner_message>"));
+        assert!(build_messages(&conversation[2..], "", &default_policy()).iter().all(|message| message.content.iter().all(|block| block.cache_control().is_none())));
+    }
+
+    #[test]
+    fn test_step_start_replays_fixture() {
+        let topic = crate::topics::get_github_setup_topic();
+        let step = topic.steps.iter().find(|step| step.id == "ssh-keys").unwrap();
+        let (conversation, system_prompt, context) = chat_request(&step.id, &[], &step.suggested_questions[0]);
+
+        let (reply, _) = tutor_reply(&step.id, &conversation, &system_prompt, &context);
+
+        assert!(reply.unwrap().text.contains("ssh-keygen -t ed25519"));
+        assert!(tutor_reply(&step.id, &conversation[..0], &system_prompt, "").0.is_err());
+    }
+
+    #[test]
+    fn test_grading_replays_fixture() {
+        let topic = crate::topics::get_github_setup_topic();
+        let exercise = crate::types::Exercise {
+            instructions: "Write a .gitignore for a Node project".to_string(),
+            rubric: vec![
+                crate::types::RubricCriterion { id: "node-modules".to_string(), description: "Ignores node_modules/".to_string(), points: 2 },
+                crate::types::RubricCriterion { id: "env-files".to_string(), description: "Ignores .env files".to_string(), points: 1 },
+            ],
+            pass_score: 0.75,
+        };
+        let template = crate::prompts::find_template(crate::prompts::EXERCISE_GRADING, crate::prompts::DEFAULT_VERSION).unwrap();
+        let system_prompt = grading::grading_system_prompt(&template, &topic.steps[7], &exercise, "English").unwrap();
+
+        let verdict = block_on(grade_exercise_answer(&system_prompt, "node_modules/\n", &replay_client())).unwrap();
+        let result = grading::parse_verdict(&verdict.text, &exercise).unwrap();
+
+        assert!(!result.passed);
+        assert_eq!(result.met_criteria, vec!["node-modules"]);
     }
 }
 
 /// Calls the Claude API with a given conversation history.
 ///
+/// The system prompt and the history before the latest message are marked as
+/// cache breakpoints, so the API can reuse them from one reply to the next.
+/// When the model calls tools, they are run by `executor` and their results sent
+/// back until the model answers. The last of the `MAX_TOOL_ROUNDS` calls does not
+/// let the model call tools, so it has to answer in text.
+///
 /// # Arguments
 ///
 /// * `conversation` - The conversation history to send to Claude
-/// * `api_key` - The API key for authentication with the Claude API
-/// * `topic` - The current DevOps topic being discussed
+/// * `client` - The client reaching the Claude API
+/// * `system_prompt` - The rendered tutor system prompt
+/// * `context` - Documentation excerpts retrieved for the latest message, empty if none
+/// * `policy` - The guardrail policy, whose prompt-injection rules mark suspected learner messages
+/// * `tools` - The tools the model may call, empty if none
+/// * `executor` - Runs the tools the model calls
 ///
 /// # Returns
 ///
-/// A `Result<String>` containing the AI's response text or an error.
-pub async fn call_claude_api_with_history(conversation: &[TimestampedChatMessage], api_key: &str, topic: &str) -> Result<String> {
-    let client = Client::new();
-    let url = "https://api.anthropic.com/v1/messages";
+/// A `Result<ClaudeReply>` containing the AI's response text or an error.
+pub async fn call_claude_api_with_history(
+    conversation: &[TimestampedChatMessage],
+    client: &ClaudeClient,
+    system_prompt: &str,
+    context: &str,
+    policy: &GuardrailPolicy,
+    tools: &[ClaudeTool],
+    executor: &mut dyn ToolExecutor,
+) -> Result<ClaudeReply> {
+    let mut request = tutor_request(conversation, system_prompt, context, policy, tools);
+    let mut usage = ClaudeUsage::default();
+    let mut tool_calls = vec![];
+    let mut round = 1;
 
-    let claude_messages: Vec<ClaudeMessage> = conversation.iter().map(|msg| ClaudeMessage {
-        role: msg.role.clone(),
-        content: msg.content.clone(),
-        name: None,
-    }).collect();
+    loop {
+        if round == MAX_TOOL_ROUNDS && !request.tools.is_empty() {
+            // The tools stay defined because the history holds tool calls, but the model must answer in text
+            request.tool_choice = Some(ClaudeToolChoice::none());
+        }
+        let response = send_request(&request, client).await?;
+        add_usage(&mut usage, &response.usage);
 
-    let system_prompt = format!(
-        "You are an AI assistant for a DevOps learning platform. Your role is to provide expert guidance and assistance on DevOps topics including:
+        let calls_tools = round < MAX_TOOL_ROUNDS
+            && response.stop_reason.as_deref() == Some("tool_use")
+            && response.content.iter().any(|block| matches!(block, ClaudeContentBlock::ToolUse { .. }));
+        if !calls_tools {
+            return Ok(ClaudeReply {
+                text: response.text(),
+                usage,
+                requests: round,
+                tool_calls,
+            });
+        }
 
-    - Version control with Git
-    - Continuous Integration and Continuous Delivery (CI/CD)
-    - Container technologies like Docker
-    - Container orchestration with Kubernetes
-    - Infrastructure as Code (IaC)
-    - Cloud platforms and services
-    - Monitoring and observability
-    - DevOps best practices and methodologies
+        let results: Vec<ClaudeContentBlock> = response.content.iter()
+            .filter_map(|block| match block {
+                ClaudeContentBlock::ToolUse { id, name, input } => {
+                    let output = executor.execute(name, input);
+                    tool_calls.push(name.clone());
+                    Some(ClaudeContentBlock::ToolResult {
+                        tool_use_id: id.clone(),
+                        content: vec![ClaudeContentBlock::text(output.content)],
+                        is_error: if output.is_error { Some(true) } else { None },
+                    })
+                }
+                _ => None,
+            })
+            .collect();
 
-    Respond to user queries with accurate, up-to-date information on these topics. Provide explanations, examples, and step-by-step instructions when appropriate. If asked about a specific tool or technology, include details on its purpose, key features, and common use cases in DevOps workflows.
+        // Blocks of unknown kinds cannot be sent back as they were received
+        request.messages.push(ClaudeMessage {
+            role: "assistant".to_string(),
+            content: response.content.into_iter()
+                .filter(|block| !matches!(block, ClaudeContentBlock::Unknown))
+                .collect(),
+            name: None,
+        });
+        request.messages.push(ClaudeMessage {
+            role: "user".to_string(),
+            content: results,
+            name: None,
+        });
+        round += 1;
+    }
+}
 
-    Important guidelines:
+/// Adds the usage of an API call to the usage of the previous rounds.
+fn add_usage(total: &mut ClaudeUsage, usage: &ClaudeUsage) {
+    let add = |total: Option<u32>, tokens: Option<u32>| match (total, tokens) {
+        (None, None) => None,
+        (total, tokens) => Some(total.unwrap_or(0) + tokens.unwrap_or(0)),
+    };
 
-    1. Only answer questions related to DevOps topics. If a user asks about an unrelated subject, politely redirect them to DevOps-relevant questions.
+    total.input_tokens += usage.input_tokens;
+    total.output_tokens += usage.output_tokens;
+    total.cache_creation_input_tokens = add(total.cache_creation_input_tokens, usage.cache_creation_input_tokens);
+    total.cache_read_input_tokens = add(total.cache_read_input_tokens, usage.cache_read_input_tokens);
+}
 
-    2. Do not provide any information on bypassing security measures, hacking, or unauthorized system access.
+/// Calls Claude with messages already converted with `build_messages`, without tools.
+///
+/// Used by the prompt evaluation to get replies the way the chat endpoint would.
+///
+/// # Arguments
+///
+/// * `system_prompt` - The rendered system prompt
+/// * `messages` - The messages to send
+/// * `client` - The client reaching the Claude API
+///
+/// # Returns
+///
+/// A `Result<ClaudeReply>` containing the reply or an error.
+pub async fn complete_messages(system_prompt: &str, messages: Vec<ClaudeMessage>, client: &ClaudeClient) -> Result<ClaudeReply> {
+    let request = ClaudeRequest {
+        model: MODEL.to_string(),
+        max_tokens: 1024,
+        messages,
+        system: vec![ClaudeContentBlock::cached_text(system_prompt)],
+        tools: vec![],
+        tool_choice: None,
+    };
+    let response = send_request(&request, client).await?;
+    Ok(ClaudeReply {
+        text: response.text(),
+        usage: response.usage,
+        requests: 1,
+        tool_calls: vec![],
+    })
+}
 
-    3. If asked to perform actions outside your capabilities (e.g. executing code, accessing external systems), explain that you're a text-based assistant focused on providing DevOps knowledge.
+/// Builds the request for a tutor reply.
+fn tutor_request(conversation: &[TimestampedChatMessage], system_prompt: &str, context: &str, policy: &GuardrailPolicy, tools: &[ClaudeTool]) -> ClaudeRequest {
+    ClaudeRequest {
+        model: MODEL.to_string(),
+        max_tokens: 1024,
+        messages: build_messages(conversation, context, policy),
+        system: vec![ClaudeContentBlock::cached_text(system_prompt)],
+        tools: tools.to_vec(),
+        tool_choice: None,
+    }
+}
 
-    4. Do not share personal information about real individuals or disclose sensitive details about specific organizations.
+/// Converts a conversation history into the messages sent to Claude.
+///
+/// Learner messages are framed so they cannot pose as instructions, and the files
+/// attached to them are sent in blocks before them. The context
+/// retrieved for the latest learner message is sent in a block before it, so the
+/// system prompt and the earlier history form a prefix that stays the same from
+/// one reply to the next; the end of that prefix is marked as a cache breakpoint.
+///
+/// # Arguments
+///
+/// * `conversation` - The conversation history
+/// * `context` - Documentation excerpts retrieved for the latest message, empty if none
+/// * `policy` - The guardrail policy, whose prompt-injection rules mark suspected learner messages
+pub fn build_messages(conversation: &[TimestampedChatMessage], context: &str, policy: &GuardrailPolicy) -> Vec<ClaudeMessage> {
+    let last = conversation.len().saturating_sub(1);
 
-    5. If unsure about an answer, acknowledge your uncertainty and suggest reliable resources for further information.
+    conversation.iter().enumerate().map(|(index, msg)| {
+        let text = if msg.role == "user" { framing::frame_learner_message(&msg.content, policy) } else { msg.content.clone() };
+        let mut content = vec![];
+        if index == last && msg.role == "user" && !context.is_empty() {
+            content.push(ClaudeContentBlock::text(context));
+        }
+        content.extend(msg.attachments.iter().map(attachment_block));
+        content.push(if index + 1 == last { ClaudeContentBlock::cached_text(text) } else { ClaudeContentBlock::text(text) });
 
-    6. Encourage best practices for security, scalability, and efficiency in DevOps processes.
+        ClaudeMessage {
+            role: msg.role.clone(),
+            content,
+            name: None,
+        }
+    }).collect()
+}
 
-    7. When discussing tools or platforms, maintain a neutral stance and focus on technical merits rather than promoting specific products.
+/// Converts a file attached to a learner message into a content block:
+/// an image block for screenshots, a framed text block for text files. Attachments
+/// whose contents were not loaded are only named, in a framed text block.
+fn attachment_block(attachment: &Attachment) -> ClaudeContentBlock {
+    if attachment.data.is_empty() {
+        ClaudeContentBlock::text(framing::frame_attachment(&attachment.name, "(attached earlier; the contents are no longer shown)"))
+    } else if attachments::is_image(attachment) {
+        ClaudeContentBlock::image(attachment.media_type.clone(), attachment.data.clone())
+    } else {
+        ClaudeContentBlock::text(framing::frame_attachment(&attachment.name, &attachment.data))
+    }
+}
 
-    Your goal is to help users learn and apply DevOps concepts effectively while maintaining a secure and focused learning environment. Please provide responses in markdown formatted in such a way that it can be rendered in a frontend react application.
+/// Asks Claude to grade a learner's answer to an exercise against its rubric.
+///
+/// The system prompt, which holds the rubric, is marked as a cache breakpoint.
+///
+/// # Arguments
+///
+/// * `system_prompt` - The rendered grading system prompt, including the rubric
+/// * `answer` - The learner's answer
+/// * `client` - The client reaching the Claude API
+///
+/// # Returns
+///
+/// A `Result<ClaudeReply>` containing the raw verdict text, to be parsed with `grading::parse_verdict`.
+pub async fn grade_exercise_answer(system_prompt: &str, answer: &str, client: &ClaudeClient) -> Result<ClaudeReply> {
+    let response = send_request(&grading_request(system_prompt, answer), client).await?;
+    Ok(ClaudeReply {
+        text: response.text(),
+        usage: response.usage,
+        requests: 1,
+        tool_calls: vec![],
+    })
+}
 
-    The current topic of discussion is: {}",
-        topic
-    );
-    let claude_request = ClaudeRequest {
-        model: "claude-3-5-sonnet-20240620".to_string(),
+/// Builds the request grading an answer.
+fn grading_request(system_prompt: &str, answer: &str) -> ClaudeRequest {
+    ClaudeRequest {
+        model: MODEL.to_string(),
         max_tokens: 1024,
-        messages: claude_messages,
-        system: Some(system_prompt),
+        messages: vec![ClaudeMessage {
+            role: "user".to_string(),
+            content: vec![ClaudeContentBlock::text(grading::grading_message(answer))],
+            name: None,
+        }],
+        system: vec![ClaudeContentBlock::cached_text(system_prompt)],
+        tools: vec![],
+        tool_choice: None,
+    }
+}
+
+/// Sends a request to the Claude Messages API.
+///
+/// Depending on the client's fixture mode, the response is served from a
+/// recorded fixture, or the API is called and the response recorded.
+///
+/// # Arguments
+///
+/// * `claude_request` - The request to send
+/// * `client` - The client reaching the Claude API
+///
+/// # Returns
+///
+/// A `Result<ClaudeResponse>` containing the response, with at least one content block, or an error.
+async fn send_request(claude_request: &ClaudeRequest, client: &ClaudeClient) -> Result<ClaudeResponse> {
+    let body = match &client.fixtures {
+        FixtureMode::Replay(dir) => fixtures::load_fixture(dir, claude_request).map_err(Error::from)?,
+        FixtureMode::Record(dir) => {
+            let body = post_request(claude_request, &client.api_key).await?;
+            fixtures::save_fixture(dir, claude_request, &body).map_err(Error::from)?;
+            body
+        }
+        FixtureMode::Live => post_request(claude_request, &client.api_key).await?,
     };
 
-    let response = match client.post(url)
+    let claude_response: ClaudeResponse = match serde_json::from_str(&body) {
+        Ok(resp) => resp,
+        Err(e) => return Err(Error::from(format!("Failed to parse API response: {}", e))),
+    };
+
+    if claude_response.content.is_empty() {
+        return Err(Error::from("No content in API response"));
+    }
+    Ok(claude_response)
+}
+
+/// Posts a request to the Claude Messages API.
+///
+/// # Returns
+///
+/// A `Result<String>` containing the body of a successful response or an error.
+async fn post_request(claude_request: &ClaudeRequest, api_key: &str) -> Result<String> {
+    let client = Client::new();
+
+    let response = match client.post(API_URL)
         .header("x-api-key", api_key)
         .header("anthropic-version", "2023-06-01")
         .header("content-type", "application/json")
-        .json(&claude_request)
+        .json(claude_request)
         .send()
         .await
     {
@@ -127,12 +618,7 @@ pub async fn call_claude_api_with_history(conversation: &[TimestampedChatMessage
         return Err(Error::from(format!("API request failed: {}", response.status())));
     }
 
-    let claude_response: ClaudeResponse = match response.json().await {
-        Ok(resp) => resp,
-        Err(e) => return Err(Error::from(format!("Failed to parse API response: {}", e))),
-    };
-    
-    claude_response.content.first()
-        .map(|content| content.text.clone())
-        .ok_or_else(|| Error::from("No content in API response"))
+    response.text().await
+        .map_err(|e| Error::from(format!("Failed to read API response: {}", e)))
 }
+
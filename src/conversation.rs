@@ -0,0 +1,450 @@
+//! This module manages stored conversation histories.
+
+use worker::*;
+use chrono::Utc;
+use crate::attachments;
+use crate::types::{ConversationHistory, ConversationPage, ConversationThread, MessageAlternate, ThreadSummary, TimestampedChatMessage};
+
+/// Default number of messages in a page of a conversation.
+pub const DEFAULT_PAGE_LIMIT: usize = 20;
+/// Maximum number of messages in a page of a conversation.
+pub const MAX_PAGE_LIMIT: usize = 50;
+
+/// Selects which page of a conversation to fetch.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PageCursor {
+    /// The most recent messages
+    Latest,
+    /// The messages immediately preceding the message with the given ID
+    Before(String),
+    /// The messages following the message with the given ID
+    After(String),
+}
+
+/// Returns the KV key of the conversation for a topic.
+pub fn conversation_key(topic_id: &str) -> String {
+    format!("conversation_{}", topic_id)
+}
+
+/// Loads the conversation for a topic, or an empty conversation if none exists.
+///
+/// Messages stored before message IDs were introduced are assigned IDs.
+pub async fn load_conversation(kv: &kv::KvStore, topic_id: &str) -> Result<ConversationHistory> {
+    let mut conversation = match kv.get(&conversation_key(topic_id)).json::<ConversationHistory>().await? {
+        Some(conversation) => conversation,
+        None => ConversationHistory {
+            topic_id: topic_id.to_string(),
+            messages: vec![],
+            next_message_id: 0,
+        },
+    };
+
+    ensure_message_ids(&mut conversation);
+    Ok(conversation)
+}
+
+/// Stores the conversation for a topic.
+pub async fn save_conversation(kv: &kv::KvStore, conversation: &ConversationHistory) -> Result<()> {
+    kv.put(&conversation_key(&conversation.topic_id), serde_json::to_string(conversation)?)?
+        .execute()
+        .await?;
+    Ok(())
+}
+
+/// Returns the KV key of the list of threads imported for a topic.
+fn threads_key(topic_id: &str) -> String {
+    format!("threads_{}", topic_id)
+}
+
+/// Returns the KV key of an imported thread.
+fn thread_key(topic_id: &str, thread_id: &str) -> String {
+    format!("thread_{}_{}", topic_id, thread_id)
+}
+
+/// Returns the scope the attachments of an imported thread are stored under,
+/// keeping them apart from those of the topic's conversation.
+pub fn thread_scope(topic_id: &str, thread_id: &str) -> String {
+    format!("{}_{}", topic_id, thread_id)
+}
+
+/// Lists the threads imported for a topic, oldest first.
+pub async fn load_threads(kv: &kv::KvStore, topic_id: &str) -> Result<Vec<ThreadSummary>> {
+    Ok(kv.get(&threads_key(topic_id)).json().await?.unwrap_or_default())
+}
+
+/// Loads an imported thread, if it exists.
+pub async fn load_thread(kv: &kv::KvStore, topic_id: &str, thread_id: &str) -> Result<Option<ConversationThread>> {
+    Ok(kv.get(&thread_key(topic_id, thread_id)).json().await?)
+}
+
+/// Stores a restored conversation as a new thread of its topic, leaving the
+/// topic's conversation untouched. The contents of its attachments are stored
+/// under the thread's scope.
+///
+/// # Arguments
+///
+/// * `kv` - The KV store
+/// * `conversation` - The restored conversation
+/// * `topic_version` - The topic version the conversation was pinned to
+///
+/// # Returns
+///
+/// The new thread.
+pub async fn create_thread(kv: &kv::KvStore, conversation: ConversationHistory, topic_version: u32) -> Result<ConversationThread> {
+    let mut threads = load_threads(kv, &conversation.topic_id).await?;
+    let mut thread = ConversationThread {
+        id: format!("t{}", threads.len() + 1),
+        imported_at: Utc::now(),
+        topic_version,
+        conversation,
+    };
+
+    let scope = thread_scope(&thread.conversation.topic_id, &thread.id);
+    for message in thread.conversation.messages.iter_mut() {
+        attachments::store_attachments(kv, &scope, message).await?;
+    }
+
+    kv.put(&thread_key(&thread.conversation.topic_id, &thread.id), serde_json::to_string(&thread)?)?
+        .execute()
+        .await?;
+    threads.push(ThreadSummary {
+        id: thread.id.clone(),
+        imported_at: thread.imported_at,
+        message_count: thread.conversation.messages.len(),
+    });
+    kv.put(&threads_key(&thread.conversation.topic_id), serde_json::to_string(&threads)?)?
+        .execute()
+        .await?;
+    Ok(thread)
+}
+
+/// Appends a message to the conversation, assigning it the next message ID.
+///
+/// # Arguments
+///
+/// * `conversation` - The conversation to append to
+/// * `role` - The role of the message sender ("user" or "assistant")
+/// * `content` - The content of the message
+/// * `step_id` - The ID of the step the learner was on when the message was sent
+///
+/// # Returns
+///
+/// The ID of the new message.
+pub fn push_message(conversation: &mut ConversationHistory, role: &str, content: String, step_id: Option<String>) -> String {
+    let id = next_message_id(conversation);
+    conversation.messages.push(TimestampedChatMessage {
+        id: id.clone(),
+        role: role.to_string(),
+        content,
+        timestamp: Utc::now(),
+        step_id,
+        attachments: vec![],
+        alternates: vec![],
+        prompt_version: None,
+    });
+    id
+}
+
+/// Replaces the final assistant message with a regenerated reply.
+///
+/// The replaced content is kept as an alternate of the message.
+///
+/// # Returns
+///
+/// The ID of the regenerated message, or an error if the conversation does not
+/// end with an assistant message.
+pub fn replace_last_reply(conversation: &mut ConversationHistory, content: String) -> std::result::Result<String, &'static str> {
+    let message = match conversation.messages.last_mut() {
+        Some(message) if message.role == "assistant" => message,
+        _ => return Err("The conversation does not end with an assistant message"),
+    };
+
+    let replaced = std::mem::replace(&mut message.content, content);
+    message.alternates.push(MessageAlternate {
+        content: replaced,
+        timestamp: message.timestamp,
+        prompt_version: message.prompt_version.take(),
+        replies: vec![],
+    });
+    message.timestamp = Utc::now();
+    Ok(message.id.clone())
+}
+
+/// Replaces the content of the latest user message, discarding the replies that followed it.
+///
+/// The replaced content and its replies are kept as an alternate of the message.
+///
+/// # Returns
+///
+/// The ID of the edited message, or an error if the conversation has no user message.
+pub fn edit_last_user_message(conversation: &mut ConversationHistory, content: String) -> std::result::Result<String, &'static str> {
+    let index = match conversation.messages.iter().rposition(|msg| msg.role == "user") {
+        Some(index) => index,
+        None => return Err("The conversation has no user message to edit"),
+    };
+
+    let replies: Vec<TimestampedChatMessage> = conversation.messages.drain(index + 1..).collect();
+    let message = &mut conversation.messages[index];
+    let replaced = std::mem::replace(&mut message.content, content);
+    message.alternates.push(MessageAlternate {
+        content: replaced,
+        timestamp: message.timestamp,
+        prompt_version: message.prompt_version.take(),
+        replies,
+    });
+    message.timestamp = Utc::now();
+    Ok(message.id.clone())
+}
+
+/// Switches a message to one of its alternates, keeping the current version as an alternate.
+///
+/// Only the latest user message and a final assistant message can be switched.
+/// Switching a user message also restores the replies that followed that version.
+pub fn select_alternate(conversation: &mut ConversationHistory, message_id: &str, alternate: usize) -> std::result::Result<(), &'static str> {
+    let index = match conversation.messages.iter().position(|msg| msg.id == message_id) {
+        Some(index) => index,
+        None => return Err("Message not found"),
+    };
+    let last_user = conversation.messages.iter().rposition(|msg| msg.role == "user");
+    let is_last = index + 1 == conversation.messages.len();
+    let is_switchable = match conversation.messages[index].role.as_str() {
+        "user" => Some(index) == last_user,
+        _ => is_last,
+    };
+    if !is_switchable {
+        return Err("Only the latest turn of the conversation can be switched");
+    }
+    if alternate >= conversation.messages[index].alternates.len() {
+        return Err("Alternate not found");
+    }
+
+    let replies: Vec<TimestampedChatMessage> = if conversation.messages[index].role == "user" {
+        conversation.messages.drain(index + 1..).collect()
+    } else {
+        vec![]
+    };
+
+    let message = &mut conversation.messages[index];
+    let selected = message.alternates.remove(alternate);
+    message.alternates.insert(alternate, MessageAlternate {
+        content: std::mem::replace(&mut message.content, selected.content),
+        timestamp: std::mem::replace(&mut message.timestamp, selected.timestamp),
+        prompt_version: std::mem::replace(&mut message.prompt_version, selected.prompt_version),
+        replies,
+    });
+    conversation.messages.extend(selected.replies);
+    Ok(())
+}
+
+/// Returns a page of a conversation.
+///
+/// Pages fetched with `Latest` or `Before` are used to load older messages;
+/// `has_more` tells whether earlier messages remain. Pages fetched with `After`
+/// are used to poll for new messages; `has_more` tells whether later messages remain.
+///
+/// # Arguments
+///
+/// * `conversation` - The conversation to page through
+/// * `cursor` - Which page to fetch
+/// * `limit` - The maximum number of messages in the page
+///
+/// A cursor message that was since dropped or replaced is placed by its number:
+/// message IDs only grow, so the page starts or ends where the message stood.
+///
+/// # Returns
+///
+/// The page, or an error if the cursor is not an ID the conversation assigned.
+pub fn page_messages(conversation: &ConversationHistory, cursor: &PageCursor, limit: usize) -> std::result::Result<ConversationPage, &'static str> {
+    // The positions of the messages before and after the cursor message
+    let bounds = |message_id: &str| -> std::result::Result<(usize, usize), &'static str> {
+        if let Some(index) = conversation.messages.iter().position(|msg| msg.id == message_id) {
+            return Ok((index, index + 1));
+        }
+        match message_number(message_id) {
+            Some(number) if number <= conversation.next_message_id => {
+                let index = conversation.messages.iter()
+                    .position(|msg| matches!(message_number(&msg.id), Some(other) if other > number))
+                    .unwrap_or(conversation.messages.len());
+                Ok((index, index))
+            }
+            _ => Err("Cursor message not found"),
+        }
+    };
+
+    let (start, end, has_more) = match cursor {
+        PageCursor::Latest | PageCursor::Before(_) => {
+            let end = match cursor {
+                PageCursor::Before(message_id) => bounds(message_id)?.0,
+                _ => conversation.messages.len(),
+            };
+            let start = end.saturating_sub(limit);
+            (start, end, start > 0)
+        }
+        PageCursor::After(message_id) => {
+            let start = bounds(message_id)?.1;
+            let end = (start + limit).min(conversation.messages.len());
+            (start, end, end < conversation.messages.len())
+        }
+    };
+
+    Ok(ConversationPage {
+        topic_id: conversation.topic_id.clone(),
+        messages: conversation.messages[start..end].to_vec(),
+        has_more,
+    })
+}
+
+/// Assigns IDs to messages that do not have one.
+pub fn ensure_message_ids(conversation: &mut ConversationHistory) {
+    for index in 0..conversation.messages.len() {
+        if conversation.messages[index].id.is_empty() {
+            conversation.messages[index].id = next_message_id(conversation);
+        }
+    }
+}
+
+/// Assigns fresh IDs to every message, including the replies kept in alternates.
+pub fn renumber_messages(conversation: &mut ConversationHistory) {
+    conversation.next_message_id = 0;
+    let mut messages = std::mem::take(&mut conversation.messages);
+    renumber(conversation, &mut messages);
+    conversation.messages = messages;
+}
+
+fn renumber(conversation: &mut ConversationHistory, messages: &mut [TimestampedChatMessage]) {
+    for message in messages.iter_mut() {
+        message.id = next_message_id(conversation);
+        for alternate in message.alternates.iter_mut() {
+            renumber(conversation, &mut alternate.replies);
+        }
+    }
+}
+
+/// Returns the number of a message ID such as `m12`.
+fn message_number(message_id: &str) -> Option<u64> {
+    message_id.strip_prefix('m')?.parse().ok()
+}
+
+fn next_message_id(conversation: &mut ConversationHistory) -> String {
+    conversation.next_message_id += 1;
+    format!("m{}", conversation.next_message_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_ids_are_unique_after_truncation() {
+        let mut conversation = ConversationHistory {
+            topic_id: "github-setup".to_string(),
+            messages: vec![TimestampedChatMessage {
+                id: String::new(),
+                role: "user".to_string(),
+                content: "Hello".to_string(),
+                timestamp: Utc::now(),
+                step_id: None,
+                attachments: vec![],
+                alternates: vec![],
+                prompt_version: None,
+            }],
+            next_message_id: 0,
+        };
+
+        ensure_message_ids(&mut conversation);
+        assert_eq!(conversation.messages[0].id, "m1");
+
+        conversation.messages.clear();
+        let id = push_message(&mut conversation, "assistant", "Hi!".to_string(), Some("create-account".to_string()));
+        assert_eq!(id, "m2");
+    }
+
+    #[test]
+    fn test_edit_and_switch_between_alternates() {
+        let mut conversation = ConversationHistory {
+            topic_id: "github-setup".to_string(),
+            messages: vec![],
+            next_message_id: 0,
+        };
+        push_message(&mut conversation, "user", "What is Git?".to_string(), None);
+        push_message(&mut conversation, "assistant", "Git is a version control system.".to_string(), None);
+
+        let regenerated = replace_last_reply(&mut conversation, "Git tracks changes to files.".to_string()).unwrap();
+        assert_eq!(regenerated, "m2");
+        assert_eq!(conversation.messages[1].alternates[0].content, "Git is a version control system.");
+
+        let edited = edit_last_user_message(&mut conversation, "What is GitHub?".to_string()).unwrap();
+        assert_eq!(edited, "m1");
+        assert_eq!(conversation.messages.len(), 1);
+        assert_eq!(conversation.messages[0].alternates[0].replies.len(), 1);
+        assert!(replace_last_reply(&mut conversation, "Nope".to_string()).is_err());
+
+        push_message(&mut conversation, "assistant", "GitHub hosts Git repositories.".to_string(), None);
+        select_alternate(&mut conversation, "m1", 0).unwrap();
+
+        assert_eq!(conversation.messages.len(), 2);
+        assert_eq!(conversation.messages[0].content, "What is Git?");
+        assert_eq!(conversation.messages[1].content, "Git tracks changes to files.");
+        assert_eq!(conversation.messages[0].alternates[0].content, "What is GitHub?");
+        assert_eq!(conversation.messages[0].alternates[0].replies[0].content, "GitHub hosts Git repositories.");
+        assert!(select_alternate(&mut conversation, "m2", 5).is_err());
+    }
+
+    #[test]
+    fn test_page_messages() {
+        let mut conversation = ConversationHistory {
+            topic_id: "github-setup".to_string(),
+            messages: vec![],
+            next_message_id: 0,
+        };
+        for index in 0..5 {
+            push_message(&mut conversation, if index % 2 == 0 { "user" } else { "assistant" }, format!("Message {}", index), None);
+        }
+        let ids = |page: &ConversationPage| page.messages.iter().map(|msg| msg.id.clone()).collect::<Vec<_>>();
+
+        let latest = page_messages(&conversation, &PageCursor::Latest, 2).unwrap();
+        assert_eq!(ids(&latest), vec!["m4", "m5"]);
+        assert!(latest.has_more);
+
+        let older = page_messages(&conversation, &PageCursor::Before("m4".to_string()), 5).unwrap();
+        assert_eq!(ids(&older), vec!["m1", "m2", "m3"]);
+        assert!(!older.has_more);
+
+        let newer = page_messages(&conversation, &PageCursor::After("m2".to_string()), 2).unwrap();
+        assert_eq!(ids(&newer), vec!["m3", "m4"]);
+        assert!(newer.has_more);
+
+        let polled = page_messages(&conversation, &PageCursor::After("m5".to_string()), 2).unwrap();
+        assert!(polled.messages.is_empty());
+        assert!(!polled.has_more);
+
+        assert!(page_messages(&conversation, &PageCursor::After("m9".to_string()), 2).is_err());
+        assert!(page_messages(&conversation, &PageCursor::After("latest".to_string()), 2).is_err());
+    }
+
+    #[test]
+    fn test_page_messages_with_stale_cursor() {
+        let mut conversation = ConversationHistory {
+            topic_id: "github-setup".to_string(),
+            messages: vec![],
+            next_message_id: 0,
+        };
+        for index in 0..4 {
+            push_message(&mut conversation, if index % 2 == 0 { "user" } else { "assistant" }, format!("Message {}", index), None);
+        }
+        // The learner edits their latest message, replacing the reply m4
+        edit_last_user_message(&mut conversation, "Message 2, edited".to_string()).unwrap();
+        push_message(&mut conversation, "assistant", "Message 3, regenerated".to_string(), None);
+        let ids = |page: &ConversationPage| page.messages.iter().map(|msg| msg.id.clone()).collect::<Vec<_>>();
+
+        let newer = page_messages(&conversation, &PageCursor::After("m4".to_string()), 5).unwrap();
+        assert_eq!(ids(&newer), vec!["m5"]);
+        assert!(!newer.has_more);
+
+        conversation.messages.remove(0);
+        let older = page_messages(&conversation, &PageCursor::Before("m1".to_string()), 5).unwrap();
+        assert!(older.messages.is_empty());
+        let newer = page_messages(&conversation, &PageCursor::After("m1".to_string()), 5).unwrap();
+        assert_eq!(ids(&newer), vec!["m2", "m3", "m5"]);
+    }
+}
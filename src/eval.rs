@@ -0,0 +1,527 @@
+//! This module contains the evaluation of prompt templates.
+//!
+//! A suite of golden cases — learner questions asked on a given topic step — is
+//! replayed through the same pipeline as the chat endpoint: the prompt template
+//! is rendered with the step context and the retrieved documentation, learner
+//! messages are framed, and follow-up questions are split from the reply. The
+//! reply comes from a pluggable `CompletionProvider`, either recorded responses
+//! or the model itself, and is scored against the case's assertions. Comparing
+//! two template versions produces a report of the cases that regressed or improved.
+
+use std::collections::HashMap;
+use std::fmt;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use crate::claude;
+use crate::followups;
+use crate::framing;
+use crate::guardrails;
+use crate::i18n;
+use crate::prompts;
+use crate::rag;
+use crate::topics;
+use crate::types::{ClaudeMessage, DocIndex, Progress, PromptTemplate, TimestampedChatMessage};
+
+pub use crate::fixtures::FixtureMode;
+
+/// Maximum length of a reply, in characters, when a case sets no limit.
+pub const DEFAULT_MAX_LENGTH: usize = 4000;
+
+/// Tags of the prompt pipeline that must never leak into a reply.
+const PIPELINE_TAGS: &[&str] = &["<learner_message", "<pasted_artifact", "<documents", "<document ", "<follow_up_questions"];
+
+/// Represents a suite of golden cases.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EvalSuite {
+    pub cases: Vec<EvalCase>,
+}
+
+/// Represents a recorded learner question and the assertions its reply must satisfy.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EvalCase {
+    /// Unique identifier of the case
+    pub id: String,
+    /// The ID of the built-in topic the question is asked on
+    pub topic_id: String,
+    /// The ID of the step the learner is on; the steps before it count as completed
+    #[serde(default)]
+    pub step_id: Option<String>,
+    /// Earlier messages of the conversation, oldest first
+    #[serde(default)]
+    pub history: Vec<EvalTurn>,
+    /// The learner's question
+    pub question: String,
+    /// What the reply must satisfy
+    #[serde(default)]
+    pub assertions: EvalAssertions,
+}
+
+/// Represents an earlier message of a golden case's conversation.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EvalTurn {
+    /// The role of the message sender ("user" or "assistant")
+    pub role: String,
+    /// The content of the message
+    pub content: String,
+}
+
+/// Represents the assertions a reply is scored against.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct EvalAssertions {
+    /// Commands or terms the reply must mention, ignoring ASCII case
+    #[serde(default)]
+    pub must_mention: Vec<String>,
+    /// Terms showing the reply stays on topic, at least one of which must appear;
+    /// defaults to the topic's tags
+    #[serde(default)]
+    pub on_topic_terms: Vec<String>,
+    /// Terms the reply must not mention, ignoring ASCII case
+    #[serde(default)]
+    pub must_not_mention: Vec<String>,
+    /// Maximum length of the reply, in characters
+    #[serde(default)]
+    pub max_length: Option<usize>,
+}
+
+/// Represents a request for a reply, as it would be sent to the model.
+#[derive(Debug)]
+pub struct EvalRequest {
+    /// The ID of the case being evaluated
+    pub case_id: String,
+    /// The template version the system prompt was rendered from, e.g. `tutor-system@1`
+    pub prompt_version: String,
+    /// The rendered system prompt
+    pub system_prompt: String,
+    /// The messages, with learner messages framed
+    pub messages: Vec<ClaudeMessage>,
+}
+
+/// A source of replies for the evaluation.
+pub trait CompletionProvider {
+    /// Returns the model's reply to a request, or a description of why there is none.
+    fn complete(&mut self, request: &EvalRequest) -> Result<String, String>;
+}
+
+/// Represents a reply recorded for a case and a template version.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RecordedResponse {
+    /// The ID of the case
+    pub case_id: String,
+    /// The template version the reply was generated with, e.g. `tutor-system@1`
+    pub prompt_version: String,
+    /// The raw reply, including any follow-up questions
+    pub response: String,
+}
+
+/// A provider serving recorded replies, so suites run without calling the model.
+pub struct RecordedProvider {
+    responses: HashMap<(String, String), String>,
+}
+
+impl RecordedProvider {
+    /// Creates a provider serving the given recorded replies.
+    pub fn new(recordings: Vec<RecordedResponse>) -> Self {
+        RecordedProvider {
+            responses: recordings.into_iter()
+                .map(|recorded| ((recorded.case_id, recorded.prompt_version), recorded.response))
+                .collect(),
+        }
+    }
+}
+
+impl CompletionProvider for RecordedProvider {
+    fn complete(&mut self, request: &EvalRequest) -> Result<String, String> {
+        self.responses.get(&(request.case_id.clone(), request.prompt_version.clone()))
+            .cloned()
+            .ok_or_else(|| format!("no recorded response for {}", request.prompt_version))
+    }
+}
+
+/// A provider calling the model, for evaluating new template versions.
+///
+/// Requests go through the same fixture layer as the chat endpoint, so a
+/// `Record` fixture mode saves every reply and `Replay` runs the suite again offline.
+#[cfg(not(target_arch = "wasm32"))]
+pub struct LiveProvider {
+    client: claude::ClaudeClient,
+    runtime: tokio::runtime::Runtime,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl LiveProvider {
+    /// Creates a provider calling the model with the given API key and fixture mode.
+    pub fn new(api_key: String, fixtures: FixtureMode) -> Result<Self, String> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| format!("failed to start the runtime: {}", e))?;
+        Ok(LiveProvider { client: claude::ClaudeClient { api_key, fixtures }, runtime })
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl CompletionProvider for LiveProvider {
+    fn complete(&mut self, request: &EvalRequest) -> Result<String, String> {
+        self.runtime.block_on(claude::complete_messages(&request.system_prompt, request.messages.clone(), &self.client))
+            .map(|reply| reply.text)
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Represents the result of one assertion.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckResult {
+    /// Name of the assertion (e.g., "mentions")
+    pub name: &'static str,
+    pub passed: bool,
+    /// What failed, empty if the assertion passed
+    pub detail: String,
+}
+
+/// Represents the result of evaluating a case with a template version.
+#[derive(Debug, Clone)]
+pub struct CaseOutcome {
+    pub case_id: String,
+    pub prompt_version: String,
+    /// The reply, without its follow-up questions
+    pub response: String,
+    pub checks: Vec<CheckResult>,
+    /// Why the case could not be evaluated, if it could not
+    pub error: Option<String>,
+}
+
+impl CaseOutcome {
+    /// Returns true if the case was evaluated and every assertion passed.
+    pub fn passed(&self) -> bool {
+        self.error.is_none() && self.checks.iter().all(|check| check.passed)
+    }
+
+    /// Describes the failed assertions, or the error.
+    fn failures(&self) -> String {
+        if let Some(error) = &self.error {
+            return format!("error: {}", error);
+        }
+        self.checks.iter()
+            .filter(|check| !check.passed)
+            .map(|check| format!("{}: {}", check.name, check.detail))
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+
+/// Evaluates every case of a suite with a template version.
+///
+/// # Arguments
+///
+/// * `suite` - The golden cases
+/// * `version` - The version of the tutor system prompt template to evaluate
+/// * `provider` - The source of replies
+///
+/// # Returns
+///
+/// The outcome of each case, in the order of the suite, or an error if the
+/// template version does not exist.
+pub fn run_suite(suite: &EvalSuite, version: u32, provider: &mut dyn CompletionProvider) -> Result<Vec<CaseOutcome>, String> {
+    let template = prompts::find_template(prompts::TUTOR_SYSTEM, version)
+        .ok_or_else(|| format!("unknown version {} of template {}", version, prompts::TUTOR_SYSTEM))?;
+    let index = rag::build_index(&rag::builtin_documents());
+
+    Ok(suite.cases.iter()
+        .map(|case| run_case(case, &template, &index, provider))
+        .collect())
+}
+
+/// Evaluates a case: renders the prompt, gets a reply and scores it.
+fn run_case(case: &EvalCase, template: &PromptTemplate, index: &DocIndex, provider: &mut dyn CompletionProvider) -> CaseOutcome {
+    let prompt_version = prompts::version_label(template);
+    let mut outcome = CaseOutcome {
+        case_id: case.id.clone(),
+        prompt_version: prompt_version.clone(),
+        response: String::new(),
+        checks: vec![],
+        error: None,
+    };
+
+    let request = match build_request(case, template, index) {
+        Ok(request) => request,
+        Err(e) => {
+            outcome.error = Some(e);
+            return outcome;
+        }
+    };
+    let reply = match provider.complete(&request) {
+        Ok(reply) => reply,
+        Err(e) => {
+            outcome.error = Some(e);
+            return outcome;
+        }
+    };
+
+    let (response, _) = followups::extract_follow_ups(&reply);
+    let topic_tags = topics::get_builtin_topic(&case.topic_id)
+        .map(|topic| topic.tags)
+        .unwrap_or_default();
+    outcome.checks = score(&response, &case.assertions, &topic_tags);
+    outcome.response = response;
+    outcome
+}
+
+/// Builds the request for a case the way the chat endpoint would.
+fn build_request(case: &EvalCase, template: &PromptTemplate, index: &DocIndex) -> Result<EvalRequest, String> {
+    let topic = topics::get_builtin_topic(&case.topic_id)
+        .ok_or_else(|| format!("unknown topic '{}'", case.topic_id))?;
+    let step_index = match &case.step_id {
+        Some(step_id) => Some(topic.steps.iter().position(|step| &step.id == step_id)
+            .ok_or_else(|| format!("unknown step '{}'", step_id))?),
+        None => None,
+    };
+
+    let mut progress = Progress::new(&topic.id, topic.version);
+    if let Some(step_index) = step_index {
+        progress.completed_steps = (0..step_index).collect();
+        progress.current_step = step_index;
+    }
+
+    let documents = rag::retrieve(index, &case.question, rag::RETRIEVAL_LIMIT);
+    let variables = prompts::tutor_variables(
+        &topic,
+        step_index.and_then(|index| topic.steps.get(index)),
+        &progress,
+        i18n::DEFAULT_LOCALE,
+        framing::framing_instruction(),
+        followups::follow_up_instruction(),
+    );
+    let system_prompt = prompts::render(&template.body, &variables)?;
+
+    let conversation: Vec<TimestampedChatMessage> = case.history.iter()
+        .map(|turn| (turn.role.as_str(), turn.content.as_str()))
+        .chain(std::iter::once(("user", case.question.as_str())))
+        .enumerate()
+        .map(|(position, (role, content))| TimestampedChatMessage {
+            id: format!("m{}", position + 1),
+            role: role.to_string(),
+            content: content.to_string(),
+            timestamp: Utc::now(),
+            step_id: case.step_id.clone(),
+            attachments: vec![],
+            alternates: vec![],
+            prompt_version: None,
+        })
+        .collect();
+
+    Ok(EvalRequest {
+        case_id: case.id.clone(),
+        prompt_version: prompts::version_label(template),
+        system_prompt,
+        messages: claude::build_messages(&conversation, &rag::context_instruction(&documents), &guardrails::default_policy()),
+    })
+}
+
+/// Scores a reply against a case's assertions.
+///
+/// # Arguments
+///
+/// * `response` - The reply, without its follow-up questions
+/// * `assertions` - The assertions of the case
+/// * `topic_tags` - The tags of the case's topic, used when the case sets no on-topic terms
+///
+/// # Returns
+///
+/// The result of each assertion.
+pub fn score(response: &str, assertions: &EvalAssertions, topic_tags: &[String]) -> Vec<CheckResult> {
+    let lowercase = response.to_ascii_lowercase();
+    let contains = |term: &String| lowercase.contains(&term.to_ascii_lowercase());
+    let check = |name: &'static str, problems: Vec<String>| CheckResult {
+        name,
+        passed: problems.is_empty(),
+        detail: problems.join(", "),
+    };
+
+    let missing: Vec<String> = assertions.must_mention.iter()
+        .filter(|term| !contains(term))
+        .map(|term| format!("missing '{}'", term))
+        .collect();
+
+    let on_topic_terms = if assertions.on_topic_terms.is_empty() { topic_tags } else { &assertions.on_topic_terms };
+    let mut off_topic: Vec<String> = assertions.must_not_mention.iter()
+        .filter(|term| contains(term))
+        .map(|term| format!("mentions '{}'", term))
+        .collect();
+    if !on_topic_terms.is_empty() && !on_topic_terms.iter().any(contains) {
+        off_topic.insert(0, format!("mentions none of {}", on_topic_terms.join(", ")));
+    }
+
+    let max_length = assertions.max_length.unwrap_or(DEFAULT_MAX_LENGTH);
+    let length = response.chars().count();
+    let too_long = if length > max_length {
+        vec![format!("{} characters, limit is {}", length, max_length)]
+    } else {
+        vec![]
+    };
+
+    vec![
+        check("mentions", missing),
+        check("on-topic", off_topic),
+        check("markdown", markdown_problems(response)),
+        check("length", too_long),
+    ]
+}
+
+/// Returns the problems that would break rendering of a reply's markdown.
+fn markdown_problems(response: &str) -> Vec<String> {
+    let mut problems = vec![];
+
+    let fences = response.lines().filter(|line| line.trim_start().starts_with("```")).count();
+    if fences % 2 == 1 {
+        problems.push("unclosed code fence".to_string());
+    }
+    if response.lines().any(|line| line.match_indices("](").any(|(index, _)| !line[index..].contains(')'))) {
+        problems.push("unclosed link".to_string());
+    }
+    let lowercase = response.to_ascii_lowercase();
+    if let Some(tag) = PIPELINE_TAGS.iter().find(|tag| lowercase.contains(*tag)) {
+        problems.push(format!("leaked {}> tag", tag));
+    }
+    if response.trim().is_empty() {
+        problems.push("empty reply".to_string());
+    }
+    problems
+}
+
+/// Represents the comparison of two template versions over a suite.
+#[derive(Debug, Clone)]
+pub struct EvalReport {
+    pub baseline: String,
+    pub candidate: String,
+    /// Pairs of outcomes for each case, baseline first
+    pub cases: Vec<(CaseOutcome, CaseOutcome)>,
+}
+
+impl EvalReport {
+    /// Pairs the outcomes of two runs of the same suite.
+    pub fn compare(baseline: Vec<CaseOutcome>, candidate: Vec<CaseOutcome>) -> Self {
+        EvalReport {
+            baseline: baseline.first().map(|outcome| outcome.prompt_version.clone()).unwrap_or_default(),
+            candidate: candidate.first().map(|outcome| outcome.prompt_version.clone()).unwrap_or_default(),
+            cases: baseline.into_iter().zip(candidate).collect(),
+        }
+    }
+
+    /// Returns the cases that pass with the baseline but fail with the candidate.
+    pub fn regressions(&self) -> Vec<&(CaseOutcome, CaseOutcome)> {
+        self.cases.iter().filter(|(baseline, candidate)| baseline.passed() && !candidate.passed()).collect()
+    }
+
+    /// Returns the cases that fail with the baseline but pass with the candidate.
+    pub fn improvements(&self) -> Vec<&(CaseOutcome, CaseOutcome)> {
+        self.cases.iter().filter(|(baseline, candidate)| !baseline.passed() && candidate.passed()).collect()
+    }
+}
+
+impl fmt::Display for EvalReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let passed = |pick: fn(&(CaseOutcome, CaseOutcome)) -> &CaseOutcome| self.cases.iter().filter(|pair| pick(pair).passed()).count();
+        writeln!(f, "{} vs {} on {} case(s)", self.baseline, self.candidate, self.cases.len())?;
+        writeln!(f, "  {}: {} passed", self.baseline, passed(|pair| &pair.0))?;
+        writeln!(f, "  {}: {} passed", self.candidate, passed(|pair| &pair.1))?;
+
+        for (baseline, candidate) in &self.cases {
+            let status = match (baseline.passed(), candidate.passed()) {
+                (true, false) => "REGRESSED",
+                (false, true) => "IMPROVED",
+                (true, true) => "pass",
+                (false, false) => "FAIL",
+            };
+            let length_change = candidate.response.chars().count() as i64 - baseline.response.chars().count() as i64;
+            writeln!(f, "{:<9} {} ({:+} characters)", status, baseline.case_id, length_change)?;
+            if !baseline.passed() {
+                writeln!(f, "          {}: {}", baseline.prompt_version, baseline.failures())?;
+            }
+            if !candidate.passed() {
+                writeln!(f, "          {}: {}", candidate.prompt_version, candidate.failures())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_case() -> EvalCase {
+        EvalCase {
+            id: "ssh-keygen".to_string(),
+            topic_id: "github-setup".to_string(),
+            step_id: Some("ssh-keys".to_string()),
+            history: vec![],
+            question: "How do I create an SSH key?".to_string(),
+            assertions: EvalAssertions {
+                must_mention: vec!["ssh-keygen".to_string()],
+                on_topic_terms: vec![],
+                must_not_mention: vec!["password123".to_string()],
+                max_length: Some(300),
+            },
+        }
+    }
+
+    #[test]
+    fn test_score() {
+        let tags = vec!["git".to_string(), "github".to_string()];
+        let assertions = sample_case().assertions;
+
+        let checks = score("Run `ssh-keygen -t ed25519` and add the public key to GitHub.", &assertions, &tags);
+        assert!(checks.iter().all(|check| check.passed));
+
+        let checks = score("Here is a recipe:\n```\nmix flour", &assertions, &tags);
+        let failed: Vec<(&str, &str)> = checks.iter()
+            .filter(|check| !check.passed)
+            .map(|check| (check.name, check.detail.as_str()))
+            .collect();
+        assert_eq!(failed, vec![
+            ("mentions", "missing 'ssh-keygen'"),
+            ("on-topic", "mentions none of git, github"),
+            ("markdown", "unclosed code fence"),
+        ]);
+    }
+
+    #[test]
+    fn test_run_suite_compares_versions() {
+        let suite = EvalSuite { cases: vec![sample_case()] };
+        let mut provider = RecordedProvider::new(vec![
+            RecordedResponse {
+                case_id: "ssh-keygen".to_string(),
+                prompt_version: "tutor-system@1".to_string(),
+                response: "Run `ssh-keygen -t ed25519` to create a key for GitHub.\n<follow_up_questions>[\"How do I add it?\"]</follow_up_questions>".to_string(),
+            },
+            RecordedResponse {
+                case_id: "ssh-keygen".to_string(),
+                prompt_version: "tutor-system@2".to_string(),
+                response: "Open the GitHub settings and create a key there.".to_string(),
+            },
+        ]);
+
+        let baseline = run_suite(&suite, 1, &mut provider).unwrap();
+        let candidate = run_suite(&suite, 2, &mut provider).unwrap();
+        assert_eq!(baseline[0].response, "Run `ssh-keygen -t ed25519` to create a key for GitHub.");
+
+        let report = EvalReport::compare(baseline, candidate);
+        assert_eq!(report.regressions().len(), 1);
+        assert!(report.to_string().contains("REGRESSED ssh-keygen"));
+        assert!(report.to_string().contains("tutor-system@2: mentions: missing 'ssh-keygen'"));
+        assert!(run_suite(&suite, 99, &mut provider).is_err());
+    }
+
+    #[test]
+    fn test_live_provider_replays_fixture() {
+        let fixtures = FixtureMode::Replay(std::path::PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/anthropic")));
+        let suite = EvalSuite { cases: vec![sample_case()] };
+        let mut provider = LiveProvider::new(String::new(), fixtures).unwrap();
+
+        let outcomes = run_suite(&suite, 1, &mut provider).unwrap();
+
+        assert_eq!(outcomes[0].error, None);
+        assert!(outcomes[0].passed());
+        assert!(outcomes[0].response.starts_with("Generate a key pair with `ssh-keygen`"));
+    }
+}
@@ -0,0 +1,152 @@
+//! This module records and replays calls to the Claude Messages API, so code
+//! calling the model can be tested without network access.
+//!
+//! Each client is given its mode: `Record` calls the API and saves each request
+//! with its response in a directory, and `Replay` serves responses from it
+//! without calling the API. Fixtures are keyed by a hash of the normalized
+//! request. Workers have no file system, so they always call the API; the
+//! command-line tools read the mode from the `ANTHROPIC_FIXTURES` environment
+//! variable, as `record:<dir>` or `replay:<dir>`.
+
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::types::ClaudeRequest;
+
+/// Environment variable selecting the fixture mode.
+pub const FIXTURES_ENV: &str = "ANTHROPIC_FIXTURES";
+
+/// Represents how calls to the Messages API are handled.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FixtureMode {
+    /// Call the API
+    Live,
+    /// Call the API and save the request and response in the directory
+    Record(PathBuf),
+    /// Serve responses saved in the directory without calling the API
+    Replay(PathBuf),
+}
+
+impl FixtureMode {
+    /// Reads the mode from the `ANTHROPIC_FIXTURES` environment variable.
+    ///
+    /// Calls are live when the variable is unset or not recognized.
+    pub fn from_env() -> Self {
+        match std::env::var(FIXTURES_ENV) {
+            Ok(value) => FixtureMode::parse(&value),
+            Err(_) => FixtureMode::Live,
+        }
+    }
+
+    /// Parses a mode such as `replay:fixtures/anthropic`.
+    fn parse(value: &str) -> Self {
+        match value.split_once(':') {
+            Some(("record", dir)) if !dir.is_empty() => FixtureMode::Record(PathBuf::from(dir)),
+            Some(("replay", dir)) if !dir.is_empty() => FixtureMode::Replay(PathBuf::from(dir)),
+            _ => FixtureMode::Live,
+        }
+    }
+}
+
+/// Represents a recorded request and the raw response the API returned.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Fixture {
+    /// The normalized request
+    pub request: Value,
+    /// The body of the API response
+    pub response: Value,
+}
+
+/// Returns the normalized form of a request.
+///
+/// Object keys are sorted, line endings are unified and strings are trimmed,
+/// so requests differing only in formatting share a fixture.
+pub fn normalize_request(request: &ClaudeRequest) -> Result<Value, String> {
+    let value = serde_json::to_value(request).map_err(|e| format!("Failed to serialize request: {}", e))?;
+    Ok(normalize_value(value))
+}
+
+/// Normalizes the strings of a JSON value. Keys are already sorted by `serde_json::Map`.
+fn normalize_value(value: Value) -> Value {
+    match value {
+        Value::String(text) => Value::String(text.replace("\r\n", "\n").trim().to_string()),
+        Value::Array(items) => Value::Array(items.into_iter().map(normalize_value).collect()),
+        Value::Object(fields) => Value::Object(fields.into_iter().map(|(key, field)| (key, normalize_value(field))).collect()),
+        other => other,
+    }
+}
+
+/// Returns the key of a request's fixture: the FNV-1a hash of its normalized form.
+pub fn request_hash(request: &ClaudeRequest) -> Result<String, String> {
+    let normalized = normalize_request(request)?.to_string();
+    let hash = normalized.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    });
+    Ok(format!("{:016x}", hash))
+}
+
+/// Returns the path of a request's fixture in a directory.
+fn fixture_path(dir: &Path, request: &ClaudeRequest) -> Result<PathBuf, String> {
+    Ok(dir.join(format!("{}.json", request_hash(request)?)))
+}
+
+/// Loads the recorded response to a request.
+///
+/// # Returns
+///
+/// The body of the recorded response, or an error if no fixture was recorded for the request.
+pub fn load_fixture(dir: &Path, request: &ClaudeRequest) -> Result<String, String> {
+    let path = fixture_path(dir, request)?;
+    let contents = std::fs::read_to_string(&path).map_err(|e| format!(
+        "No fixture for this request at {} ({}); record it with {}=record:{}",
+        path.display(),
+        e,
+        FIXTURES_ENV,
+        dir.display()
+    ))?;
+    let fixture: Fixture = serde_json::from_str(&contents)
+        .map_err(|e| format!("Invalid fixture {}: {}", path.display(), e))?;
+    Ok(fixture.response.to_string())
+}
+
+/// Saves a request with the response the API returned.
+pub fn save_fixture(dir: &Path, request: &ClaudeRequest, response: &str) -> Result<(), String> {
+    let fixture = Fixture {
+        request: normalize_request(request)?,
+        response: serde_json::from_str(response).map_err(|e| format!("Response is not valid JSON: {}", e))?,
+    };
+    let path = fixture_path(dir, request)?;
+    let contents = serde_json::to_string_pretty(&fixture).map_err(|e| format!("Failed to serialize fixture: {}", e))?;
+
+    std::fs::create_dir_all(dir)
+        .and_then(|_| std::fs::write(&path, contents + "\n"))
+        .map_err(|e| format!("Failed to write fixture {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{ClaudeMessage, ClaudeContentBlock};
+
+    fn sample_request(content: &str) -> ClaudeRequest {
+        ClaudeRequest {
+            model: "claude-3-5-sonnet-20240620".to_string(),
+            max_tokens: 1024,
+            messages: vec![ClaudeMessage { role: "user".to_string(), content: vec![ClaudeContentBlock::text(content)], name: None }],
+            system: vec![ClaudeContentBlock::cached_text("You are a tutor.")],
+            tools: vec![],
+            tool_choice: None,
+        }
+    }
+
+    #[test]
+    fn test_request_hash_ignores_formatting() {
+        let hash = request_hash(&sample_request("What is Git?")).unwrap();
+
+        assert_eq!(hash.len(), 16);
+        assert_eq!(request_hash(&sample_request("What is Git?\r\n")).unwrap(), hash);
+        assert_ne!(request_hash(&sample_request("What is GitHub?")).unwrap(), hash);
+        assert_eq!(FixtureMode::parse("replay:fixtures/anthropic"), FixtureMode::Replay(PathBuf::from("fixtures/anthropic")));
+        assert_eq!(FixtureMode::parse("replay:"), FixtureMode::Live);
+    }
+}
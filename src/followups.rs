@@ -0,0 +1,137 @@
+//! This module handles the follow-up questions suggested to the learner after each answer.
+//!
+//! The model is asked to append contextual follow-up questions to its answer in
+//! a delimited block. The block is stripped from the answer and the questions are
+//! merged with the curated questions of the current step.
+
+/// Tag delimiting the follow-up questions block in the model's reply.
+const FOLLOW_UP_TAG: &str = "follow_up_questions";
+
+/// Maximum number of suggested questions returned to the learner.
+pub const MAX_SUGGESTED_QUESTIONS: usize = 5;
+
+/// Returns the system prompt instruction asking the model for follow-up questions.
+pub fn follow_up_instruction() -> String {
+    format!(
+        "After your answer, suggest up to 3 short follow-up questions the learner might want to ask next, written from the learner's point of view and in the same language as your answer. Put them at the very end of your reply as a JSON array of strings inside <{tag}></{tag}> tags, for example:
+    <{tag}>[\"How do I undo my last commit?\"]</{tag}>",
+        tag = FOLLOW_UP_TAG
+    )
+}
+
+/// Splits the model's reply into the answer and its follow-up questions.
+///
+/// Replies without a well-formed block, such as a reply cut off before the
+/// closing tag or one whose block is not a JSON array of strings, are returned
+/// unchanged with no questions.
+///
+/// # Arguments
+///
+/// * `reply` - The raw text returned by the model
+///
+/// # Returns
+///
+/// A tuple of the answer text and the follow-up questions.
+pub fn extract_follow_ups(reply: &str) -> (String, Vec<String>) {
+    let open = format!("<{}>", FOLLOW_UP_TAG);
+    let close = format!("</{}>", FOLLOW_UP_TAG);
+
+    let unchanged = || (reply.to_string(), vec![]);
+    let start = match reply.rfind(&open) {
+        Some(start) => start,
+        None => return unchanged(),
+    };
+    let block_end = match reply[start..].find(&close) {
+        Some(end) => start + end,
+        None => return unchanged(),
+    };
+    let questions: Vec<String> = match serde_json::from_str::<Vec<String>>(reply[start + open.len()..block_end].trim()) {
+        Ok(questions) => questions.into_iter()
+            .map(|question| question.trim().to_string())
+            .filter(|question| !question.is_empty())
+            .collect(),
+        Err(_) => return unchanged(),
+    };
+
+    let answer = format!("{}{}", &reply[..start], &reply[block_end + close.len()..]).trim_end().to_string();
+    (answer, questions)
+}
+
+/// Merges AI-generated and curated questions into the suggestions shown to the learner.
+///
+/// AI-generated questions come first, followed by the curated questions of the
+/// current step. Duplicates and questions the learner has already asked are removed.
+///
+/// # Arguments
+///
+/// * `generated` - Follow-up questions generated by the model
+/// * `curated` - Curated questions of the current step
+/// * `asked` - Messages the learner has already sent
+pub fn merge_suggestions(generated: &[String], curated: &[String], asked: &[String]) -> Vec<String> {
+    let mut seen: Vec<String> = asked.iter().map(|question| normalize_question(question)).collect();
+    let mut suggestions = vec![];
+
+    for question in generated.iter().chain(curated.iter()) {
+        let normalized = normalize_question(question);
+        if normalized.is_empty() || seen.contains(&normalized) {
+            continue;
+        }
+        seen.push(normalized);
+        suggestions.push(question.clone());
+        if suggestions.len() == MAX_SUGGESTED_QUESTIONS {
+            break;
+        }
+    }
+
+    suggestions
+}
+
+/// Normalizes a question for duplicate detection, ignoring case, punctuation and spacing.
+fn normalize_question(question: &str) -> String {
+    question.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_follow_ups() {
+        let reply = "Use `git clone <url>`.\n\n<follow_up_questions>[\"What is a remote?\", \" \"]</follow_up_questions>";
+
+        let (answer, questions) = extract_follow_ups(reply);
+
+        assert_eq!(answer, "Use `git clone <url>`.");
+        assert_eq!(questions, vec!["What is a remote?"]);
+
+        let (answer, questions) = extract_follow_ups("No suggestions here.");
+        assert_eq!(answer, "No suggestions here.");
+        assert!(questions.is_empty());
+
+        for reply in [
+            "Run `git init`.\n\n<follow_up_questions>[\"What does git init crea",
+            "Run `git init`.\n\n<follow_up_questions>[What is a repository?]</follow_up_questions>",
+        ] {
+            assert_eq!(extract_follow_ups(reply), (reply.to_string(), vec![]));
+        }
+    }
+
+    #[test]
+    fn test_merge_suggestions() {
+        let generated = vec!["What is a remote?".to_string(), "Can I undo a commit?".to_string()];
+        let curated = vec![
+            "Can I undo a commit".to_string(),
+            "How often should I commit my changes?".to_string(),
+        ];
+        let asked = vec!["what is a REMOTE".to_string()];
+
+        assert_eq!(merge_suggestions(&generated, &curated, &asked), vec![
+            "Can I undo a commit?".to_string(),
+            "How often should I commit my changes?".to_string(),
+        ]);
+    }
+}
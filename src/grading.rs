@@ -0,0 +1,142 @@
+//! This module builds the prompts for AI-graded exercises and validates the
+//! verdicts returned by the model.
+//!
+//! The model only decides which rubric criteria an answer meets; the score is
+//! computed here from the rubric points so it cannot be inflated by the model.
+
+use std::collections::HashMap;
+use chrono::Utc;
+use crate::framing;
+use crate::prompts;
+use crate::types::{Exercise, ExerciseResult, ExerciseVerdict, PromptTemplate, Step};
+
+/// Maximum length of a learner's answer, in characters.
+pub const MAX_ANSWER_LENGTH: usize = 8000;
+
+/// Builds the system prompt instructing the model to grade an answer.
+///
+/// # Arguments
+///
+/// * `template` - The version of the exercise grading template to render
+/// * `step` - The step the exercise belongs to
+/// * `exercise` - The exercise, including its rubric
+/// * `language` - The language the feedback should be written in
+///
+/// # Returns
+///
+/// The system prompt, or a description of why the template could not be rendered.
+pub fn grading_system_prompt(template: &PromptTemplate, step: &Step, exercise: &Exercise, language: &str) -> Result<String, String> {
+    let rubric = exercise.rubric.iter()
+        .map(|criterion| format!("- {} ({} point(s)): {}", criterion.id, criterion.points, criterion.description))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let variables = HashMap::from([
+        ("step", step.title.clone()),
+        ("instructions", exercise.instructions.clone()),
+        ("rubric", rubric),
+        ("language", language.to_string()),
+    ]);
+    prompts::render(&template.body, &variables)
+}
+
+/// Wraps the learner's answer in the user message sent to the model.
+///
+/// Tags in the answer are neutralized so it cannot close the block early.
+pub fn grading_message(answer: &str) -> String {
+    format!("<answer>\n{}\n</answer>", framing::neutralize_tags(answer))
+}
+
+/// Parses and validates the model's verdict, and scores it against the rubric.
+///
+/// The verdict may be wrapped in a markdown code fence or surrounded by text;
+/// the outermost JSON object is used.
+///
+/// # Arguments
+///
+/// * `text` - The raw text returned by the model
+/// * `exercise` - The exercise that was graded
+///
+/// # Returns
+///
+/// The `ExerciseResult`, or a description of why the verdict is invalid.
+pub fn parse_verdict(text: &str, exercise: &Exercise) -> Result<ExerciseResult, String> {
+    let start = text.find('{').ok_or("verdict contains no JSON object")?;
+    let end = text.rfind('}').ok_or("verdict contains no JSON object")?;
+    if end < start {
+        return Err("verdict contains no JSON object".to_string());
+    }
+
+    let verdict: ExerciseVerdict = serde_json::from_str(&text[start..=end])
+        .map_err(|e| format!("verdict is not valid JSON: {}", e))?;
+
+    if verdict.feedback.trim().is_empty() {
+        return Err("verdict has no feedback".to_string());
+    }
+    if let Some(unknown) = verdict.met_criteria.iter().find(|id| !exercise.rubric.iter().any(|criterion| &criterion.id == *id)) {
+        return Err(format!("verdict references unknown criterion '{}'", unknown));
+    }
+
+    let mut met_criteria = verdict.met_criteria;
+    met_criteria.sort();
+    met_criteria.dedup();
+
+    let total: u32 = exercise.rubric.iter().map(|criterion| criterion.points).sum();
+    let awarded: u32 = exercise.rubric.iter()
+        .filter(|criterion| met_criteria.contains(&criterion.id))
+        .map(|criterion| criterion.points)
+        .sum();
+    let score = if total == 0 { 0.0 } else { awarded as f32 / total as f32 };
+
+    Ok(ExerciseResult {
+        score,
+        passed: total > 0 && score >= exercise.pass_score,
+        met_criteria,
+        feedback: verdict.feedback.trim().to_string(),
+        missing_points: verdict.missing_points.into_iter().filter(|point| !point.trim().is_empty()).collect(),
+        graded_at: Utc::now(),
+        warnings: vec![],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::RubricCriterion;
+
+    fn sample_exercise() -> Exercise {
+        Exercise {
+            instructions: "Write a .gitignore for a Node project".to_string(),
+            rubric: vec![
+                RubricCriterion { id: "node-modules".to_string(), description: "Ignores node_modules/".to_string(), points: 2 },
+                RubricCriterion { id: "env-files".to_string(), description: "Ignores .env files".to_string(), points: 1 },
+                RubricCriterion { id: "logs".to_string(), description: "Ignores log files".to_string(), points: 1 },
+            ],
+            pass_score: 0.75,
+        }
+    }
+
+    #[test]
+    fn test_parse_verdict() {
+        let text = "```json\n{\"met_criteria\": [\"node-modules\", \"env-files\"], \"feedback\": \"Good start!\", \"missing_points\": [\"Ignore npm-debug.log\"]}\n```";
+
+        let result = parse_verdict(text, &sample_exercise()).unwrap();
+
+        assert_eq!(result.score, 0.75);
+        assert!(result.passed);
+        assert_eq!(result.feedback, "Good start!");
+        assert_eq!(result.missing_points, vec!["Ignore npm-debug.log"]);
+    }
+
+    #[test]
+    fn test_parse_verdict_rejects_invalid_verdicts() {
+        let exercise = sample_exercise();
+
+        assert!(parse_verdict("I cannot grade this.", &exercise).is_err());
+        assert!(parse_verdict("{\"met_criteria\": [], \"feedback\": \" \"}", &exercise).is_err());
+        assert_eq!(
+            parse_verdict("{\"met_criteria\": [\"docker\"], \"feedback\": \"Nice\"}", &exercise).unwrap_err(),
+            "verdict references unknown criterion 'docker'"
+        );
+    }
+}
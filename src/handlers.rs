@@ -1,50 +1,132 @@
 //! This module contains handler functions for all API endpoints.
 
 use worker::*;
-use crate::types::{Topic, Step, Progress, ProgressUpdate, ChatMessage, ChatResponse, GenericResponse, ConversationHistory, TimestampedChatMessage};
+use crate::types::{Attachment, ClaudeTool, Topic, Step, Difficulty, TopicQuery, Progress, ProgressUpdate, ChatMessage, ChatResponse, Citation, SourceDocument, GuardrailPolicy, GuardrailStage, PromptExperiment, PromptTemplate, ConversationHistory, TimestampedChatMessage, SelectAlternateRequest, ExportFormat, ConversationArchive, GenericResponse, Preferences, QuizSubmission, ExerciseSubmission, ValidationErrorResponse, FeedbackRequest, MessageFeedback, Rating};
+use crate::attachments;
 use crate::claude;
+use crate::conversation;
+use crate::export;
+use crate::followups;
+use crate::framing;
+use crate::grading;
+use crate::guardrails;
+use crate::i18n;
+use crate::prompts;
+use crate::quiz;
+use crate::rag;
+use crate::redaction;
+use crate::search;
+use crate::tools;
 use crate::topics;
-use chrono::Utc;
+use crate::usage;
+use crate::utils;
+use crate::validation::{self, ValidationReport};
+use crate::versioning;
+use chrono::{NaiveDate, Utc};
+
+/// Key of the KV entry holding the learner's preferences.
+const PREFERENCES_KEY: &str = "preferences";
+
+/// Maximum length of a feedback comment, in characters.
+const MAX_FEEDBACK_COMMENT_LENGTH: usize = 1000;
+
+/// Error returned when step indices refer to a topic version that is no longer
+/// archived, after the learner's progress was migrated to the current version
+/// and stored.
+const MIGRATED_PROGRESS_ERROR: &str = "Your progress was moved to the latest version of this topic; reload the topic and try again";
 
 /// Handles GET request for all topics.
 ///
+/// Supports optional `tag`, `difficulty` and `q` query parameters to filter
+/// and search the topic catalogue. Topics are returned in the negotiated locale.
+///
 /// # Arguments
 ///
-/// * `_req` - The incoming request (unused)
-/// * `_ctx` - The route context (unused)
+/// * `req` - The incoming request containing the optional query parameters
+/// * `ctx` - The route context used to access the data store
 ///
 /// # Returns
 ///
-/// A `Result<Response>` containing a JSON array of all topics.
-pub async fn handle_get_topics(_req: Request, _ctx: RouteContext<()>) -> Result<Response> {
+/// A `Result<Response>` containing a JSON array of the matching topics.
+pub async fn handle_get_topics(req: Request, ctx: RouteContext<()>) -> Result<Response> {
     console_log!("Handling GET request to /api/topics");
 
-    let topics = topics::get_all_topics();
-    Response::from_json(&topics)
+    let mut query = TopicQuery::default();
+    for (key, value) in req.url()?.query_pairs() {
+        if value.trim().is_empty() {
+            continue;
+        }
+        match key.as_ref() {
+            "tag" => query.tag = Some(value.to_string()),
+            "q" => query.q = Some(value.to_string()),
+            "difficulty" => match value.parse::<Difficulty>() {
+                Ok(difficulty) => query.difficulty = Some(difficulty),
+                Err(e) => return Response::error(e, 400),
+            },
+            _ => {}
+        }
+    }
+    console_log!("Topic query: {:?}", query);
+
+    let kv = ctx.kv("DATA_STORE")?;
+    let locale = negotiate_request_locale(&req, &kv).await?;
+    let localized: Vec<Topic> = topics::load_all_topics(&kv, false).await?
+        .iter()
+        .map(|topic| {
+            let mut localized = i18n::localize_topic(topic, locale);
+            quiz::strip_answer_keys(&mut localized);
+            localized
+        })
+        .collect();
+
+    Response::from_json(&topics::search_topics(localized, &query))
 }
 
 /// Handles GET request for a specific topic.
 ///
+/// An optional `version` query parameter selects an earlier version of the topic,
+/// as used by learners whose progress is pinned to it. The topic is returned in
+/// the negotiated locale.
+///
 /// # Arguments
 ///
-/// * `_req` - The incoming request (unused)
+/// * `req` - The incoming request containing the optional version
 /// * `ctx` - The route context containing the topic ID
 ///
 /// # Returns
 ///
 /// A `Result<Response>` containing a JSON object of the requested topic or a 404 error.
-pub async fn handle_get_topic(_req: Request, ctx: RouteContext<()>) -> Result<Response> {
+pub async fn handle_get_topic(req: Request, ctx: RouteContext<()>) -> Result<Response> {
     console_log!("Handling GET request to /api/topics/:topicId");
 
     let topic_id: &str = ctx.param("topicId").map(|s| s.as_str()).unwrap_or("");
     console_log!("Requested topic ID: {}", topic_id);
 
-    let topic = match topic_id {
-        "github-setup" => topics::get_github_setup_topic(),
-        _ => return Response::error("Topic not found", 404),
+    let kv = ctx.kv("DATA_STORE")?;
+    let topic = match topics::load_published_topic(&kv, topic_id).await? {
+        Some(topic) => topic,
+        None => return Response::error("Topic not found", 404),
     };
 
-    Response::from_json(&topic)
+    let version = req.url()?.query_pairs()
+        .find(|(key, _)| key == "version")
+        .map(|(_, value)| value.parse::<u32>());
+
+    let topic = match version {
+        None => topic,
+        Some(Ok(version)) if version >= 1 && version <= topic.version => {
+            match topics::load_topic_version(&kv, &topic, version).await? {
+                Some(topic) => topic,
+                None => return Response::error("Topic version not found", 404),
+            }
+        }
+        Some(_) => return Response::error("Invalid topic version", 400),
+    };
+
+    let locale = negotiate_request_locale(&req, &kv).await?;
+    let mut localized = i18n::localize_topic(&topic, locale);
+    quiz::strip_answer_keys(&mut localized);
+    Response::from_json(&localized)
 }
 
 /// Handles POST request to update progress for a topic.
@@ -63,9 +145,11 @@ pub async fn handle_post_progress(mut req: Request, ctx: RouteContext<()>) -> Re
     let topic_id: String = ctx.param("topicId").map(|s| s.to_string()).unwrap_or_default();
     console_log!("Topic ID for progress update: {}", topic_id);
 
-    if !topic_exists(&topic_id) {
-        return Response::error("Topic not found", 404);
-    }
+    let kv = ctx.kv("DATA_STORE")?;
+    let topic = match topics::load_published_topic(&kv, &topic_id).await? {
+        Some(topic) => topic,
+        None => return Response::error("Topic not found", 404),
+    };
 
     let progress_update: ProgressUpdate = match req.json().await {
         Ok(update) => update,
@@ -75,25 +159,33 @@ pub async fn handle_post_progress(mut req: Request, ctx: RouteContext<()>) -> Re
         }
     };
 
-    let kv = ctx.kv("DATA_STORE")?;
     let mut progress: Progress = match kv.get(&topic_id).json().await? {
         Some(p) => p,
-        None => Progress {
-            topic_id: topic_id.clone(),
-            completed_steps: vec![],
-            current_step: 0,
-        },
+        None => Progress::new(&topic_id, topic.version),
     };
 
     console_log!("Current progress before update: {:?}", progress);
 
-    if progress.completed_steps.contains(&progress_update.completed_step) {
+    // Step indices are relative to the topic version the learner started on
+    let pinned_version = progress.topic_version;
+    let pinned_topic = load_pinned_topic(&kv, &topic, &mut progress).await?;
+    if progress.topic_version != pinned_version {
+        return reject_migrated_progress(&kv, &progress).await;
+    }
+    match pinned_topic.steps.get(progress_update.completed_step) {
+        Some(step) if step.quiz.is_some() => {
+            return Response::error("Quiz steps are completed by submitting a passing quiz", 400);
+        }
+        Some(step) if step.exercise.is_some() => {
+            return Response::error("Exercise steps are completed by submitting a passing answer", 400);
+        }
+        Some(_) => {}
+        None => return Response::error("Step not found", 404),
+    }
+
+    if !versioning::mark_step_completed(&mut progress, &pinned_topic, progress_update.completed_step) {
         console_log!("Step {} already completed", progress_update.completed_step);
     } else {
-        progress.completed_steps.push(progress_update.completed_step);
-        progress.completed_steps.sort(); // Ensure the list is always sorted
-        progress.current_step = progress_update.completed_step + 1;
-        
         console_log!("Updated progress: {:?}", progress);
 
         kv.put(&topic_id, serde_json::to_string(&progress)?)?
@@ -122,28 +214,252 @@ pub async fn handle_get_progress(_req: Request, ctx: RouteContext<()>) -> Result
     let topic_id: String = ctx.param("topicId").map(|s| s.to_string()).unwrap_or_default();
     console_log!("Requested progress for topic ID: {}", topic_id);
 
-    if !topic_exists(&topic_id) {
-        return Response::error("Topic not found", 404);
+    let kv = ctx.kv("DATA_STORE")?;
+    let topic = match topics::load_published_topic(&kv, &topic_id).await? {
+        Some(topic) => topic,
+        None => return Response::error("Topic not found", 404),
+    };
+
+    let progress: Progress = match kv.get(&topic_id).json().await? {
+        Some(p) => p,
+        None => Progress::new(&topic_id, topic.version),
+    };
+
+    Response::from_json(&progress)
+}
+
+/// Handles POST request to submit answers to a quiz step.
+///
+/// The submission is graded against the answer key of the step in the topic
+/// version the learner's progress is pinned to. The step is only marked as
+/// completed when the quiz is passed.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request containing the quiz submission
+/// * `ctx` - The route context containing the topic ID and step index
+///
+/// # Returns
+///
+/// A `Result<Response>` containing the graded quiz result or an error.
+pub async fn handle_post_quiz(mut req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling POST request to /api/quiz/:topicId/:stepIndex");
+
+    let topic_id: String = ctx.param("topicId").map(|s| s.to_string()).unwrap_or_default();
+    let step_index: Option<usize> = ctx.param("stepIndex").and_then(|s| s.parse().ok());
+
+    let kv = ctx.kv("DATA_STORE")?;
+    let topic = match topics::load_published_topic(&kv, &topic_id).await? {
+        Some(topic) => topic,
+        None => return Response::error("Topic not found", 404),
+    };
+
+    let submission: QuizSubmission = match req.json().await {
+        Ok(submission) => submission,
+        Err(e) => {
+            console_error!("Error parsing quiz submission: {:?}", e);
+            return Response::error("Invalid JSON input", 400);
+        }
+    };
+
+    let mut progress: Progress = match kv.get(&topic_id).json().await? {
+        Some(p) => p,
+        None => Progress::new(&topic_id, topic.version),
+    };
+
+    let pinned_version = progress.topic_version;
+    let pinned_topic = load_pinned_topic(&kv, &topic, &mut progress).await?;
+    if progress.topic_version != pinned_version {
+        return reject_migrated_progress(&kv, &progress).await;
     }
+    let (step_index, step) = match step_index.and_then(|index| pinned_topic.steps.get(index).map(|step| (index, step))) {
+        Some(found) => found,
+        None => return Response::error("Step not found", 404),
+    };
+    let step_quiz = match &step.quiz {
+        Some(step_quiz) => step_quiz,
+        None => return Response::error("Step is not a quiz", 400),
+    };
+
+    let result = quiz::grade_quiz(step_quiz, &submission);
+    console_log!("Quiz for step {} of topic {} scored {}", step.id, topic_id, result.score);
+
+    kv.put(&format!("quiz_{}_{}", topic_id, step.id), serde_json::to_string(&result)?)?
+        .execute().await?;
+
+    if result.passed && versioning::mark_step_completed(&mut progress, &pinned_topic, step_index) {
+        kv.put(&topic_id, serde_json::to_string(&progress)?)?
+            .execute().await?;
+    }
+
+    Response::from_json(&result)
+}
+
+/// Handles POST request to submit an answer to an exercise step.
+///
+/// The answer is graded by the AI against the rubric of the step in the topic
+/// version the learner's progress is pinned to, after secrets are removed from
+/// it. The step is only marked as completed when the answer passes.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request containing the answer
+/// * `ctx` - The route context containing the topic ID and step index
+///
+/// # Returns
+///
+/// A `Result<Response>` containing the graded exercise result or an error.
+pub async fn handle_post_exercise(mut req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling POST request to /api/exercise/:topicId/:stepIndex");
+
+    let topic_id: String = ctx.param("topicId").map(|s| s.to_string()).unwrap_or_default();
+    let step_index: Option<usize> = ctx.param("stepIndex").and_then(|s| s.parse().ok());
+
+    let kv = ctx.kv("DATA_STORE")?;
+    let topic = match topics::load_published_topic(&kv, &topic_id).await? {
+        Some(topic) => topic,
+        None => return Response::error("Topic not found", 404),
+    };
+
+    let submission: ExerciseSubmission = match req.json().await {
+        Ok(submission) => submission,
+        Err(e) => {
+            console_error!("Error parsing exercise submission: {:?}", e);
+            return Response::error("Invalid JSON input", 400);
+        }
+    };
+
+    if submission.answer.trim().is_empty() {
+        return Response::error("Answer cannot be empty", 400);
+    }
+    if submission.answer.chars().count() > grading::MAX_ANSWER_LENGTH {
+        return Response::error(format!("Answer must be at most {} characters", grading::MAX_ANSWER_LENGTH), 400);
+    }
+
+    let mut progress: Progress = match kv.get(&topic_id).json().await? {
+        Some(p) => p,
+        None => Progress::new(&topic_id, topic.version),
+    };
+
+    let pinned_version = progress.topic_version;
+    let pinned_topic = load_pinned_topic(&kv, &topic, &mut progress).await?;
+    if progress.topic_version != pinned_version {
+        return reject_migrated_progress(&kv, &progress).await;
+    }
+    let (step_index, step) = match step_index.and_then(|index| pinned_topic.steps.get(index).map(|step| (index, step))) {
+        Some(found) => found,
+        None => return Response::error("Step not found", 404),
+    };
+    let exercise = match &step.exercise {
+        Some(exercise) => exercise,
+        None => return Response::error("Step is not an exercise", 400),
+    };
+
+    let client = claude::ClaudeClient::live(ctx.secret("ANTHROPIC_API_KEY")?.to_string());
+    let locale = negotiate_request_locale(&req, &kv).await?;
+
+    let learner_id = utils::learner_id(&req)?;
+    let template = prompts::resolve_template(&kv, prompts::EXERCISE_GRADING, learner_id.as_deref()).await?;
+    let system_prompt = match grading::grading_system_prompt(&template, step, exercise, i18n::language_name(locale)) {
+        Ok(system_prompt) => system_prompt,
+        Err(e) => {
+            console_error!("Error rendering prompt template {}: {}", prompts::version_label(&template), e);
+            return Response::error("Failed to grade answer", 500);
+        }
+    };
+
+    // Secrets are removed before the answer reaches the model, as they are from chat messages
+    let (answer, secrets) = redaction::redact_secrets(&submission.answer);
+    if !secrets.is_empty() {
+        console_log!("Redacted {} kind(s) of secret from an exercise answer for topic ID: {}", secrets.len(), topic_id);
+    }
+
+    let verdict = match claude::grade_exercise_answer(&system_prompt, &answer, &client).await {
+        Ok(verdict) => verdict,
+        Err(e) => {
+            console_error!("Error calling Claude API: {:?}", e);
+            return Response::error("Failed to grade answer", 500);
+        }
+    };
+    usage::record_usage(&kv, usage::GRADING, &verdict.usage, verdict.requests).await?;
+
+    let mut result = match grading::parse_verdict(&verdict.text, exercise) {
+        Ok(result) => result,
+        Err(e) => {
+            console_error!("Invalid grading verdict: {}", e);
+            return Response::error("Failed to grade answer", 502);
+        }
+    };
+    console_log!("Exercise for step {} of topic {} scored {}", step.id, topic_id, result.score);
+    if !secrets.is_empty() {
+        result.warnings.push(redaction::redaction_warning(&secrets));
+    }
+
+    kv.put(&format!("exercise_{}_{}", topic_id, step.id), serde_json::to_string(&result)?)?
+        .execute().await?;
+
+    if result.passed && versioning::mark_step_completed(&mut progress, &pinned_topic, step_index) {
+        kv.put(&topic_id, serde_json::to_string(&progress)?)?
+            .execute().await?;
+    }
+
+    Response::from_json(&result)
+}
+
+/// Handles POST request to migrate progress to the latest version of a topic.
+///
+/// # Arguments
+///
+/// * `_req` - The incoming request (unused)
+/// * `ctx` - The route context containing the topic ID
+///
+/// # Returns
+///
+/// A `Result<Response>` containing the migrated progress or an error.
+pub async fn handle_migrate_progress(_req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling POST request to /api/progress/:topicId/migrate");
+
+    let topic_id: String = ctx.param("topicId").map(|s| s.to_string()).unwrap_or_default();
 
     let kv = ctx.kv("DATA_STORE")?;
+    let topic = match topics::load_published_topic(&kv, &topic_id).await? {
+        Some(topic) => topic,
+        None => return Response::error("Topic not found", 404),
+    };
+
     let progress: Progress = match kv.get(&topic_id).json().await? {
         Some(p) => p,
-        None => Progress {
-            topic_id: topic_id.clone(),
-            completed_steps: vec![],
-            current_step: 0,
-        },
+        None => return Response::from_json(&Progress::new(&topic_id, topic.version)),
     };
 
-    Response::from_json(&progress)
+    if progress.topic_version == topic.version {
+        console_log!("Progress for topic {} is already on version {}", topic_id, topic.version);
+        return Response::from_json(&progress);
+    }
+
+    let migrated = match topics::load_topic_version(&kv, &topic, progress.topic_version).await? {
+        Some(pinned_topic) => versioning::migrate_progress(&progress, &pinned_topic, &topic),
+        None => {
+            console_warn!("Version {} of topic {} is not archived, migrating by step ids", progress.topic_version, topic_id);
+            versioning::migrate_unarchived_progress(&progress, &topic)
+        }
+    };
+    console_log!("Migrated progress from version {} to {}: {:?}", progress.topic_version, topic.version, migrated);
+
+    kv.put(&topic_id, serde_json::to_string(&migrated)?)?
+        .execute().await?;
+
+    Response::from_json(&migrated)
 }
 
 /// Handles POST request for chat messages.
 ///
+/// The message can carry screenshots and text files, which are stored with it
+/// and sent to the model along with it.
+///
 /// # Arguments
 ///
-/// * `req` - The incoming request containing the chat message
+/// * `req` - The incoming request containing the chat message and its attachments
 /// * `ctx` - The route context containing the topic ID
 ///
 /// # Returns
@@ -155,9 +471,11 @@ pub async fn handle_post_chat(mut req: Request, ctx: RouteContext<()>) -> Result
     let topic_id: String = ctx.param("topicId").map(|s| s.to_string()).unwrap_or_default();
     console_log!("Chat message for topic ID: {}", topic_id);
 
-    if !topic_exists(&topic_id) {
-        return Response::error("Topic not found", 404);
-    }
+    let kv = ctx.kv("DATA_STORE")?;
+    let topic = match topics::load_published_topic(&kv, &topic_id).await? {
+        Some(topic) => topic,
+        None => return Response::error("Topic not found", 404),
+    };
 
     let chat_message: ChatMessage = match req.json().await {
         Ok(message) => message,
@@ -167,156 +485,1653 @@ pub async fn handle_post_chat(mut req: Request, ctx: RouteContext<()>) -> Result
         }
     };
 
-    if chat_message.message.trim().is_empty() {
+    if chat_message.message.trim().is_empty() && chat_message.attachments.is_empty() {
         return Response::error("Message cannot be empty", 400);
     }
 
-    let kv = ctx.kv("DATA_STORE")?;
-    let conversation_key = format!("conversation_{}", topic_id);
+    let problems = attachments::validate_attachments(&chat_message.attachments);
+    if !problems.is_empty() {
+        return Ok(Response::from_json(&ValidationErrorResponse {
+            status: 422,
+            message: "Attachments failed validation".to_string(),
+            errors: problems,
+        })?.with_status(422));
+    }
+    let mut attachments = chat_message.attachments;
+
+    // Check the message against the guardrails before it reaches the model
+    let inbound = check_inbound(&kv, &topic_id, &chat_message.message, &mut attachments).await?;
+    if let Some(reason) = inbound.blocked {
+        return Response::error(reason, 400);
+    }
 
     // Retrieve existing conversation or create a new one
-    let mut conversation: ConversationHistory = match kv.get(&conversation_key).json().await? {
-        Some(c) => c,
-        None => ConversationHistory {
-            topic_id: topic_id.clone(),
-            messages: vec![],
-        },
-    };
+    let mut conversation = conversation::load_conversation(&kv, &topic_id).await?;
 
-    // Add the new user message to the conversation
-    conversation.messages.push(TimestampedChatMessage {
-        role: "user".to_string(),
-        content: chat_message.message.clone(),
-        timestamp: Utc::now(),
-    });
+    let attached_size: usize = attachments.iter().map(attachments::attachment_size).sum();
+    if attached_size > 0 && attachments::conversation_size(&conversation) + attached_size > attachments::MAX_CONVERSATION_BYTES {
+        return Ok(Response::from_json(&ValidationErrorResponse {
+            status: 422,
+            message: "Attachments failed validation".to_string(),
+            errors: vec![format!(
+                "the attachments of a conversation can total at most {} bytes; reset the conversation to attach more files",
+                attachments::MAX_CONVERSATION_BYTES
+            )],
+        })?.with_status(422));
+    }
 
-    let api_key = ctx.secret("ANTHROPIC_API_KEY")?.to_string();
-
-    // Call Claude API with the full conversation history
-    match claude::call_claude_api_with_history(&conversation.messages, &api_key, &topic_id).await {
-        Ok(response) => {
-            // Add Claude's response to the conversation history
-            conversation.messages.push(TimestampedChatMessage {
-                role: "assistant".to_string(),
-                content: response.clone(),
-                timestamp: Utc::now(),
-            });
-
-            // Implement conversation management strategy (e.g., truncation)
-            if conversation.messages.len() > 50 {  // Adjust this number as needed
-                conversation.messages = conversation.messages.split_off(conversation.messages.len() - 50);
-            }
+    let chat_context = load_chat_context(&req, &kv, &topic).await?;
+    let step_id = chat_context.current_step.as_ref().map(|step| step.id.clone());
 
-            // Store the updated conversation
-            kv.put(&conversation_key, serde_json::to_string(&conversation)?)?
-              .execute()
-              .await?;
-
-            // Get the current step and its suggested questions
-            let progress: Progress = match kv.get(&topic_id).json().await? {
-                Some(p) => p,
-                None => Progress {
-                    topic_id: topic_id.clone(),
-                    completed_steps: vec![],
-                    current_step: 0,
-                },
-            };
-
-            let topic = topics::get_github_setup_topic();
-            let suggested_questions = if progress.current_step < topic.steps.len() {
-                topic.steps[progress.current_step].suggested_questions.clone()
-            } else {
-                vec![]
-            };
-
-            Response::from_json(&ChatResponse { 
-                response,
-                suggested_questions,
-            })
-        },
+    // Add the new user message to the conversation, storing its attachments under their own keys
+    conversation::push_message(&mut conversation, "user", inbound.text, step_id.clone());
+    if let Some(message) = conversation.messages.last_mut() {
+        message.attachments = attachments;
+        attachments::store_attachments(&kv, &topic_id, message).await?;
+    }
+
+    let mut reply = match generate_reply(&ctx, &kv, &conversation.messages, &topic_id, &chat_context).await {
+        Ok(reply) => reply,
         Err(e) => {
             console_error!("Error calling Claude API: {:?}", e);
-            Response::error("Failed to generate response", 500)
+            return Response::error("Failed to generate response", 500);
         }
-    }
+    };
+    reply.warnings.splice(0..0, inbound.warnings);
+
+    // Add Claude's response to the conversation history
+    let message_id = conversation::push_message(&mut conversation, "assistant", reply.response.clone(), step_id);
+
+    store_and_respond(&kv, conversation, &chat_context, message_id, reply).await
 }
 
-/// Handles POST request to reset progress for a topic.
+/// Handles POST request to regenerate the latest assistant reply.
+///
+/// The replaced reply is kept as an alternate of the message.
 ///
 /// # Arguments
 ///
-/// * `_req` - The incoming request (unused)
+/// * `req` - The incoming request
 /// * `ctx` - The route context containing the topic ID
 ///
 /// # Returns
 ///
-/// A `Result<Response>` confirming the progress reset or an error.
-pub async fn handle_reset_progress(_req: Request, ctx: RouteContext<()>) -> Result<Response> {
-    console_log!("Handling POST request to /api/reset/:topicId");
+/// A `Result<Response>` containing the regenerated response or an error.
+pub async fn handle_regenerate_chat(req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling POST request to /api/chat/:topicId/regenerate");
 
     let topic_id: String = ctx.param("topicId").map(|s| s.to_string()).unwrap_or_default();
-    console_log!("Resetting progress and conversation for topic ID: {}", topic_id);
-
-    if !topic_exists(&topic_id) {
-        return Response::error("Topic not found", 404);
-    }
 
     let kv = ctx.kv("DATA_STORE")?;
-    
-    // Reset progress
-    let progress = Progress {
-        topic_id: topic_id.clone(),
-        completed_steps: vec![],
-        current_step: 0,
+    let topic = match topics::load_published_topic(&kv, &topic_id).await? {
+        Some(topic) => topic,
+        None => return Response::error("Topic not found", 404),
     };
 
-    kv.put(&topic_id, serde_json::to_string(&progress)?)?
-        .execute().await?;
+    let mut conversation = conversation::load_conversation(&kv, &topic_id).await?;
+    if conversation.messages.last().map(|msg| msg.role.as_str()) != Some("assistant") {
+        return Response::error("There is no reply to regenerate", 400);
+    }
+    let chat_context = load_chat_context(&req, &kv, &topic).await?;
+
+    // Regenerate from the history leading up to the reply being replaced
+    let history = &conversation.messages[..conversation.messages.len() - 1];
+    let reply = match generate_reply(&ctx, &kv, history, &topic_id, &chat_context).await {
+        Ok(reply) => reply,
+        Err(e) => {
+            console_error!("Error calling Claude API: {:?}", e);
+            return Response::error("Failed to generate response", 500);
+        }
+    };
 
-    // Reset conversation history
-    let conversation_key = format!("conversation_{}", topic_id);
-    kv.delete(&conversation_key).await?;
+    let message_id = match conversation::replace_last_reply(&mut conversation, reply.response.clone()) {
+        Ok(message_id) => message_id,
+        Err(e) => return Response::error(e, 400),
+    };
 
-    Response::from_json(&GenericResponse {
-        status: 200,
-        message: format!("Progress and conversation reset for topic {}.", topic_id),
-    })
+    store_and_respond(&kv, conversation, &chat_context, message_id, reply).await
 }
 
-/// Checks if a topic exists.
+/// Handles POST request to edit the latest user message.
+///
+/// The replies that followed the message are discarded and a new reply is
+/// generated. The replaced message and its replies are kept as an alternate.
 ///
 /// # Arguments
 ///
-/// * `topic_id` - The ID of the topic to check
+/// * `req` - The incoming request containing the edited message
+/// * `ctx` - The route context containing the topic ID
 ///
 /// # Returns
 ///
-/// A boolean indicating whether the topic exists.
-fn topic_exists(topic_id: &str) -> bool {
-    matches!(topic_id, "github-setup" | "docker-basics")
+/// A `Result<Response>` containing the AI's response to the edited message or an error.
+pub async fn handle_edit_chat(mut req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling POST request to /api/chat/:topicId/edit");
+
+    let topic_id: String = ctx.param("topicId").map(|s| s.to_string()).unwrap_or_default();
+
+    let kv = ctx.kv("DATA_STORE")?;
+    let topic = match topics::load_published_topic(&kv, &topic_id).await? {
+        Some(topic) => topic,
+        None => return Response::error("Topic not found", 404),
+    };
+
+    let chat_message: ChatMessage = match req.json().await {
+        Ok(message) => message,
+        Err(e) => {
+            console_error!("Error parsing chat message: {:?}", e);
+            return Response::error("Invalid JSON input", 400);
+        }
+    };
+
+    if chat_message.message.trim().is_empty() {
+        return Response::error("Message cannot be empty", 400);
+    }
+    if !chat_message.attachments.is_empty() {
+        return Response::error("Attachments cannot be changed when editing a message", 400);
+    }
+
+    let inbound = check_inbound(&kv, &topic_id, &chat_message.message, &mut []).await?;
+    if let Some(reason) = inbound.blocked {
+        return Response::error(reason, 400);
+    }
+
+    let mut conversation = conversation::load_conversation(&kv, &topic_id).await?;
+    if let Err(e) = conversation::edit_last_user_message(&mut conversation, inbound.text) {
+        return Response::error(e, 400);
+    }
+    let chat_context = load_chat_context(&req, &kv, &topic).await?;
+    let step_id = chat_context.current_step.as_ref().map(|step| step.id.clone());
+
+    let mut reply = match generate_reply(&ctx, &kv, &conversation.messages, &topic_id, &chat_context).await {
+        Ok(reply) => reply,
+        Err(e) => {
+            console_error!("Error calling Claude API: {:?}", e);
+            return Response::error("Failed to generate response", 500);
+        }
+    };
+    reply.warnings.splice(0..0, inbound.warnings);
+
+    let message_id = conversation::push_message(&mut conversation, "assistant", reply.response.clone(), step_id);
+
+    store_and_respond(&kv, conversation, &chat_context, message_id, reply).await
 }
 
-pub async fn handle_get_conversation(_req: Request, ctx: RouteContext<()>) -> Result<Response> {
-    console_log!("Handling GET request to /api/conversation/:topicId");
+/// The learner's locale, pinned topic version, progress, prompt template and
+/// the tools the tutor may call, used when generating replies.
+struct ChatContext {
+    locale: &'static str,
+    topic: Topic,
+    progress: Progress,
+    current_step: Option<Step>,
+    prompt: PromptTemplate,
+    tools: Vec<ClaudeTool>,
+}
 
-    let topic_id: String = ctx.param("topicId").map(|s| s.to_string()).unwrap_or_default();
-    console_log!("Retrieving conversation for topic ID: {}", topic_id);
+/// Loads the locale, the pinned topic version with the current step, the
+/// tutor prompt template version assigned to the learner, and the tools allowed
+/// by the current version of the topic.
+async fn load_chat_context(req: &Request, kv: &kv::KvStore, topic: &Topic) -> Result<ChatContext> {
+    let mut progress: Progress = match kv.get(&topic.id).json().await? {
+        Some(p) => p,
+        None => Progress::new(&topic.id, topic.version),
+    };
+    let locale = negotiate_request_locale(req, kv).await?;
+    let pinned_topic = load_pinned_topic(kv, topic, &mut progress).await?;
+    let pinned_topic = i18n::localize_topic(&pinned_topic, locale);
 
-    if !topic_exists(&topic_id) {
-        return Response::error("Topic not found", 404);
+    let learner_id = utils::learner_id(req)?;
+    let prompt = prompts::resolve_template(kv, prompts::TUTOR_SYSTEM, learner_id.as_deref()).await?;
+
+    Ok(ChatContext {
+        locale,
+        current_step: pinned_topic.steps.get(progress.current_step).cloned(),
+        topic: pinned_topic,
+        progress,
+        prompt,
+        tools: tools::allowed_tools(topic),
+    })
+}
+
+/// A reply generated by the model, with its follow-up questions, citations,
+/// guardrail warnings, the prompt template version used and the tools called.
+struct GeneratedReply {
+    response: String,
+    follow_ups: Vec<String>,
+    citations: Vec<Citation>,
+    warnings: Vec<String>,
+    prompt_version: String,
+    tool_calls: Vec<String>,
+    /// The learner's progress, if the tools changed it; stored only once the reply is accepted
+    progress: Option<Progress>,
+}
+
+/// Removes secrets from a learner message and its attached text files, then
+/// checks the message against the guardrail policy and records the triggered rules.
+async fn check_inbound(kv: &kv::KvStore, topic_id: &str, message: &str, attached: &mut [Attachment]) -> Result<guardrails::GuardrailOutcome> {
+    let (message, mut secrets) = redaction::redact_secrets(message);
+    secrets.extend(attachments::redact_attachments(attached));
+    secrets.sort();
+    secrets.dedup();
+
+    let policy = guardrails::load_policy(kv).await?;
+    let mut outcome = guardrails::check(&policy, GuardrailStage::Inbound, &message);
+    guardrails::record_triggered(kv, &outcome, GuardrailStage::Inbound, topic_id).await?;
+
+    if !secrets.is_empty() {
+        console_log!("Redacted {} kind(s) of secret from a message for topic ID: {}", secrets.len(), topic_id);
+        outcome.warnings.insert(0, redaction::redaction_warning(&secrets));
     }
+    Ok(outcome)
+}
 
-    let kv = ctx.kv("DATA_STORE")?;
-    let conversation_key = format!("conversation_{}", topic_id);
+/// Calls Claude API with the conversation history, grounded in the documentation
+/// relevant to the latest user message, and separates the generated follow-up
+/// questions from the answer itself. Secrets are removed from the answer and the
+/// questions, and both are checked against the guardrail policy before they are
+/// returned; blocked questions are dropped. Progress changed by the tools the model called
+/// is returned with a reply that passes the guardrails, to be stored with it.
+async fn generate_reply(ctx: &RouteContext<()>, kv: &kv::KvStore, history: &[TimestampedChatMessage], topic_id: &str, chat_context: &ChatContext) -> Result<GeneratedReply> {
+    let query = history.iter().rev()
+        .find(|msg| msg.role == "user")
+        .map(|msg| msg.content.as_str())
+        .unwrap_or("");
+    let index = rag::load_index(kv).await?;
+    let documents = rag::retrieve(&index, query, rag::RETRIEVAL_LIMIT);
 
-    match kv.get(&conversation_key).json::<ConversationHistory>().await? {
-        Some(conversation) => Response::from_json(&conversation),
-        None => {
-            let empty_conversation = ConversationHistory {
-                topic_id: topic_id.clone(),
-                messages: vec![],
-            };
-            Response::from_json(&empty_conversation)
+    let client = claude::ClaudeClient::live(ctx.secret("ANTHROPIC_API_KEY")?.to_string());
+    let variables = prompts::tutor_variables(
+        &chat_context.topic,
+        chat_context.current_step.as_ref(),
+        &chat_context.progress,
+        chat_context.locale,
+        framing::framing_instruction(),
+        followups::follow_up_instruction(),
+    );
+    let prompt_version = prompts::version_label(&chat_context.prompt);
+    let system_prompt = prompts::render(&chat_context.prompt.body, &variables)
+        .map_err(|e| Error::from(format!("Failed to render prompt template {}: {}", prompt_version, e)))?;
+
+    let policy = guardrails::load_policy(kv).await?;
+    if let Some(latest) = history.iter().rev().find(|msg| msg.role == "user") {
+        let signals = framing::detect_injection(&latest.content, &policy);
+        if !signals.is_empty() {
+            console_log!("Possible prompt injection in message {}: {:?}", latest.id, signals);
         }
     }
+
+    // Only the attachments of the latest messages are sent to the model
+    let mut history = history.to_vec();
+    attachments::load_recent_attachments(kv, topic_id, &mut history).await?;
+
+    let mut session = tools::ToolSession::new(&chat_context.topic, &index, chat_context.progress.clone());
+    let reply = claude::call_claude_api_with_history(
+        &history,
+        &client,
+        &system_prompt,
+        &rag::context_instruction(&documents),
+        &policy,
+        &chat_context.tools,
+        &mut session,
+    ).await?;
+    usage::record_usage(kv, usage::CHAT, &reply.usage, reply.requests).await?;
+    if !reply.tool_calls.is_empty() {
+        console_log!("Tutor called tools {:?} for topic ID: {}", reply.tool_calls, topic_id);
+    }
+    let progress = if session.progress_changed { Some(session.progress) } else { None };
+    let (response, follow_ups) = followups::extract_follow_ups(&reply.text);
+
+    // Secrets the model echoes back, from the history or a tool result, are redacted too
+    let (response, secrets) = redaction::redact_secrets(&response);
+    if !secrets.is_empty() {
+        console_log!("Redacted {} kind(s) of secret from a reply for topic ID: {}", secrets.len(), topic_id);
+    }
+
+    let outbound = guardrails::check(&policy, GuardrailStage::Outbound, &response);
+    guardrails::record_triggered(kv, &outbound, GuardrailStage::Outbound, topic_id).await?;
+    if outbound.blocked.is_some() {
+        console_log!("Reply blocked by guardrails for topic ID: {}", topic_id);
+        return Ok(GeneratedReply {
+            response: guardrails::BLOCKED_REPLY.to_string(),
+            follow_ups: vec![],
+            citations: vec![],
+            warnings: outbound.warnings,
+            prompt_version,
+            tool_calls: reply.tool_calls,
+            progress: None,
+        });
+    }
+
+    // Follow-up questions come from the same output, so they are checked too; blocked ones are dropped
+    let mut checked_follow_ups = vec![];
+    for question in follow_ups {
+        let outcome = guardrails::check(&policy, GuardrailStage::Outbound, &redaction::redact_secrets(&question).0);
+        guardrails::record_triggered(kv, &outcome, GuardrailStage::Outbound, topic_id).await?;
+        if outcome.blocked.is_none() {
+            checked_follow_ups.push(outcome.text);
+        }
+    }
+
+    let citations = rag::extract_citations(&outbound.text, &documents);
+    Ok(GeneratedReply {
+        response: outbound.text,
+        follow_ups: checked_follow_ups,
+        citations,
+        warnings: outbound.warnings,
+        prompt_version,
+        tool_calls: reply.tool_calls,
+        progress,
+    })
+}
+
+/// Stores the updated conversation, and the progress changed by the tutor's
+/// tools if any, then builds the chat response.
+async fn store_and_respond(
+    kv: &kv::KvStore,
+    mut conversation: ConversationHistory,
+    chat_context: &ChatContext,
+    message_id: String,
+    reply: GeneratedReply,
+) -> Result<Response> {
+    if let Some(message) = conversation.messages.iter_mut().find(|msg| msg.id == message_id) {
+        message.prompt_version = Some(reply.prompt_version.clone());
+    }
+
+    // Implement conversation management strategy (e.g., truncation)
+    if conversation.messages.len() > 50 {  // Adjust this number as needed
+        let kept = conversation.messages.split_off(conversation.messages.len() - 50);
+        for dropped in std::mem::replace(&mut conversation.messages, kept) {
+            attachments::delete_attachments(kv, &conversation.topic_id, &dropped).await?;
+        }
+    }
+
+    // Store the updated conversation
+    conversation::save_conversation(kv, &conversation).await?;
+
+    if let Some(progress) = &reply.progress {
+        console_log!("Progress updated by the tutor for topic ID: {}", conversation.topic_id);
+        kv.put(&conversation.topic_id, serde_json::to_string(progress)?)?
+            .execute().await?;
+    }
+
+    let curated_questions = chat_context.current_step.as_ref()
+        .map(|step| step.suggested_questions.clone())
+        .unwrap_or_default();
+
+    let asked: Vec<String> = conversation.messages.iter()
+        .filter(|msg| msg.role == "user")
+        .map(|msg| msg.content.clone())
+        .collect();
+    let suggested_questions = followups::merge_suggestions(&reply.follow_ups, &curated_questions, &asked);
+
+    Response::from_json(&ChatResponse {
+        message_id,
+        response: reply.response,
+        suggested_questions,
+        citations: reply.citations,
+        warnings: reply.warnings,
+        tool_calls: reply.tool_calls,
+    })
+}
+
+/// Handles POST request to reset progress for a topic.
+///
+/// # Arguments
+///
+/// * `_req` - The incoming request (unused)
+/// * `ctx` - The route context containing the topic ID
+///
+/// # Returns
+///
+/// A `Result<Response>` confirming the progress reset or an error.
+pub async fn handle_reset_progress(_req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling POST request to /api/reset/:topicId");
+
+    let topic_id: String = ctx.param("topicId").map(|s| s.to_string()).unwrap_or_default();
+    console_log!("Resetting progress and conversation for topic ID: {}", topic_id);
+
+    let kv = ctx.kv("DATA_STORE")?;
+    let topic = match topics::load_published_topic(&kv, &topic_id).await? {
+        Some(topic) => topic,
+        None => return Response::error("Topic not found", 404),
+    };
+
+    // Reset progress, pinning it to the latest version of the topic
+    let progress = Progress::new(&topic_id, topic.version);
+
+    kv.put(&topic_id, serde_json::to_string(&progress)?)?
+        .execute().await?;
+
+    // Reset conversation history, with the attachments of its messages. The message
+    // counter is kept so that new messages don't reuse the IDs feedback refers to.
+    let mut conversation = conversation::load_conversation(&kv, &topic_id).await?;
+    for message in std::mem::take(&mut conversation.messages) {
+        attachments::delete_attachments(&kv, &topic_id, &message).await?;
+    }
+    conversation::save_conversation(&kv, &conversation).await?;
+
+    Response::from_json(&GenericResponse {
+        status: 200,
+        message: format!("Progress and conversation reset for topic {}.", topic_id),
+    })
+}
+
+/// Handles GET request to retrieve the conversation for a topic.
+///
+/// Without query parameters the whole conversation is returned. The `before`
+/// and `after` parameters, which take a message ID, and `limit` return a page
+/// of messages instead: `before` loads older messages, and `after` fetches the
+/// messages sent since the given one.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request containing the optional paging parameters
+/// * `ctx` - The route context containing the topic ID
+///
+/// # Returns
+///
+/// A `Result<Response>` containing the conversation, a page of it, or an error.
+pub async fn handle_get_conversation(req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling GET request to /api/conversation/:topicId");
+
+    let topic_id: String = ctx.param("topicId").map(|s| s.to_string()).unwrap_or_default();
+    console_log!("Retrieving conversation for topic ID: {}", topic_id);
+
+    let mut cursor: Option<conversation::PageCursor> = None;
+    let mut limit: Option<usize> = None;
+    for (key, value) in req.url()?.query_pairs() {
+        let next_cursor = match key.as_ref() {
+            "before" => conversation::PageCursor::Before(value.to_string()),
+            "after" => conversation::PageCursor::After(value.to_string()),
+            "limit" => {
+                match value.parse::<usize>() {
+                    Ok(value) if value >= 1 => limit = Some(value.min(conversation::MAX_PAGE_LIMIT)),
+                    _ => return Response::error("Invalid limit", 400),
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        if cursor.replace(next_cursor).is_some() {
+            return Response::error("Only one of before and after can be given", 400);
+        }
+    }
+
+    let kv = ctx.kv("DATA_STORE")?;
+    if topics::load_published_topic(&kv, &topic_id).await?.is_none() {
+        return Response::error("Topic not found", 404);
+    }
+
+    let conversation = conversation::load_conversation(&kv, &topic_id).await?;
+    if cursor.is_none() && limit.is_none() {
+        return Response::from_json(&conversation);
+    }
+
+    let cursor = cursor.unwrap_or(conversation::PageCursor::Latest);
+    match conversation::page_messages(&conversation, &cursor, limit.unwrap_or(conversation::DEFAULT_PAGE_LIMIT)) {
+        Ok(page) => Response::from_json(&page),
+        Err(e) => Response::error(e, 404),
+    }
+}
+
+/// Handles GET request to search the learner's conversations.
+///
+/// Searches the conversations of every published topic, and the threads imported
+/// for them, for the keywords in the `q` query parameter. The optional `topic` parameter restricts the search to
+/// one topic and `limit` caps the number of results.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request containing the search parameters
+/// * `ctx` - The route context used to access the data store
+///
+/// # Returns
+///
+/// A `Result<Response>` containing a JSON array of the matching messages or an error.
+pub async fn handle_search_conversations(req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling GET request to /api/conversations/search");
+
+    let mut query = String::new();
+    let mut topic_filter: Option<String> = None;
+    let mut limit = search::DEFAULT_SEARCH_LIMIT;
+    for (key, value) in req.url()?.query_pairs() {
+        match key.as_ref() {
+            "q" => query = value.to_string(),
+            "topic" if !value.trim().is_empty() => topic_filter = Some(value.to_string()),
+            "limit" => match value.parse::<usize>() {
+                Ok(value) if value >= 1 => limit = value.min(search::MAX_SEARCH_LIMIT),
+                _ => return Response::error("Invalid limit", 400),
+            },
+            _ => {}
+        }
+    }
+
+    if query.trim().is_empty() {
+        return Response::error("Search query cannot be empty", 400);
+    }
+    console_log!("Searching conversations for: {}", query);
+
+    let kv = ctx.kv("DATA_STORE")?;
+    let locale = negotiate_request_locale(&req, &kv).await?;
+    let mut conversations = vec![];
+    for topic in topics::load_all_topics(&kv, false).await? {
+        if matches!(&topic_filter, Some(filter) if filter != &topic.id) {
+            continue;
+        }
+        let topic = i18n::localize_topic(&topic, locale);
+        let conversation = conversation::load_conversation(&kv, &topic.id).await?;
+        if !conversation.messages.is_empty() {
+            conversations.push((topic.clone(), None, conversation));
+        }
+        for summary in conversation::load_threads(&kv, &topic.id).await? {
+            if let Some(thread) = conversation::load_thread(&kv, &topic.id, &summary.id).await? {
+                conversations.push((topic.clone(), Some(thread.id), thread.conversation));
+            }
+        }
+    }
+
+    Response::from_json(&search::search_conversations(&conversations, &query, limit))
+}
+
+/// Handles GET request to export the conversation for a topic.
+///
+/// The `format` query parameter selects a Markdown study-notes document
+/// (the default), a standalone HTML page or a JSON archive that can be imported
+/// again. Step titles are given in the negotiated locale.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request containing the optional format
+/// * `ctx` - The route context containing the topic ID
+///
+/// # Returns
+///
+/// A `Result<Response>` containing the exported conversation as a download or an error.
+pub async fn handle_export_conversation(req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling GET request to /api/conversation/:topicId/export");
+
+    let topic_id: String = ctx.param("topicId").map(|s| s.to_string()).unwrap_or_default();
+
+    let format = match req.url()?.query_pairs().find(|(key, _)| key == "format") {
+        Some((_, value)) => match value.parse::<ExportFormat>() {
+            Ok(format) => format,
+            Err(e) => return Response::error(e, 400),
+        },
+        None => ExportFormat::Markdown,
+    };
+    console_log!("Exporting conversation for topic ID {} as {:?}", topic_id, format);
+
+    let kv = ctx.kv("DATA_STORE")?;
+    let topic = match topics::load_published_topic(&kv, &topic_id).await? {
+        Some(topic) => topic,
+        None => return Response::error("Topic not found", 404),
+    };
+
+    // Step headings follow the topic version the learner's progress is pinned to
+    let mut progress: Progress = match kv.get(&topic_id).json().await? {
+        Some(p) => p,
+        None => Progress::new(&topic_id, topic.version),
+    };
+    let locale = negotiate_request_locale(&req, &kv).await?;
+    let pinned_topic = load_pinned_topic(&kv, &topic, &mut progress).await?;
+    let pinned_topic = i18n::localize_topic(&pinned_topic, locale);
+
+    let mut conversation = conversation::load_conversation(&kv, &topic_id).await?;
+    if format == ExportFormat::Json {
+        // JSON archives carry the attachments, so they can be imported again
+        for message in conversation.messages.iter_mut() {
+            attachments::load_attachments(&kv, &topic_id, message).await?;
+        }
+    }
+    let archive = export::build_archive(&conversation, &pinned_topic);
+
+    let (response, content_type, extension) = match format {
+        ExportFormat::Markdown => (Response::ok(export::render_markdown(&archive, &pinned_topic))?, "text/markdown; charset=utf-8", "md"),
+        ExportFormat::Html => (Response::from_html(export::render_html(&archive, &pinned_topic))?, "text/html; charset=utf-8", "html"),
+        ExportFormat::Json => (Response::from_json(&archive)?, "application/json", "json"),
+    };
+
+    let mut headers = Headers::new();
+    headers.set("Content-Type", content_type)?;
+    headers.set("Content-Disposition", &format!("attachment; filename=\"{}-conversation.{}\"", topic_id, extension))?;
+    Ok(response.with_headers(headers))
+}
+
+/// Handles POST request to import a conversation from a JSON archive.
+///
+/// The archive is restored as a new thread of the topic, with fresh message
+/// IDs, next to the topic's conversation, which is left untouched.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request containing the archive
+/// * `ctx` - The route context containing the topic ID
+///
+/// # Returns
+///
+/// A `Result<Response>` containing the new thread or an error.
+pub async fn handle_import_conversation(mut req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling POST request to /api/conversation/:topicId/import");
+
+    let topic_id: String = ctx.param("topicId").map(|s| s.to_string()).unwrap_or_default();
+
+    let kv = ctx.kv("DATA_STORE")?;
+    if topics::load_published_topic(&kv, &topic_id).await?.is_none() {
+        return Response::error("Topic not found", 404);
+    }
+
+    let archive: ConversationArchive = match req.json().await {
+        Ok(archive) => archive,
+        Err(e) => {
+            console_error!("Error parsing conversation archive: {:?}", e);
+            return Response::error("Invalid JSON input", 400);
+        }
+    };
+
+    let issues = export::validate_archive(&archive, &topic_id, Utc::now());
+    if !issues.is_empty() {
+        return Ok(Response::from_json(&ValidationErrorResponse {
+            status: 422,
+            message: "Conversation archive failed validation".to_string(),
+            errors: issues.iter().map(|issue| issue.to_string()).collect(),
+        })?.with_status(422));
+    }
+
+    let topic_version = archive.topic_version;
+    let thread = conversation::create_thread(&kv, export::restore_archive(archive), topic_version).await?;
+    console_log!("Imported {} messages as thread {} for topic ID: {}", thread.conversation.messages.len(), thread.id, topic_id);
+
+    Ok(Response::from_json(&thread)?.with_status(201))
+}
+
+/// Handles GET request to list the threads imported for a topic.
+///
+/// # Arguments
+///
+/// * `_req` - The incoming request (unused)
+/// * `ctx` - The route context containing the topic ID
+///
+/// # Returns
+///
+/// A `Result<Response>` containing the threads, oldest first, or an error.
+pub async fn handle_get_threads(_req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling GET request to /api/conversation/:topicId/threads");
+
+    let topic_id: String = ctx.param("topicId").map(|s| s.to_string()).unwrap_or_default();
+
+    let kv = ctx.kv("DATA_STORE")?;
+    if topics::load_published_topic(&kv, &topic_id).await?.is_none() {
+        return Response::error("Topic not found", 404);
+    }
+
+    Response::from_json(&conversation::load_threads(&kv, &topic_id).await?)
+}
+
+/// Handles GET request to retrieve a thread imported for a topic.
+///
+/// # Arguments
+///
+/// * `_req` - The incoming request (unused)
+/// * `ctx` - The route context containing the topic ID and thread ID
+///
+/// # Returns
+///
+/// A `Result<Response>` containing the thread or an error.
+pub async fn handle_get_thread(_req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling GET request to /api/conversation/:topicId/threads/:threadId");
+
+    let topic_id: String = ctx.param("topicId").map(|s| s.to_string()).unwrap_or_default();
+    let thread_id: String = ctx.param("threadId").map(|s| s.to_string()).unwrap_or_default();
+
+    let kv = ctx.kv("DATA_STORE")?;
+    if topics::load_published_topic(&kv, &topic_id).await?.is_none() {
+        return Response::error("Topic not found", 404);
+    }
+
+    match conversation::load_thread(&kv, &topic_id, &thread_id).await? {
+        Some(thread) => Response::from_json(&thread),
+        None => Response::error("Thread not found", 404),
+    }
+}
+
+/// Handles POST request to switch a message to one of its alternates.
+///
+/// Only the latest user message and a final assistant message can be switched.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request containing the index of the alternate
+/// * `ctx` - The route context containing the topic ID and message ID
+///
+/// # Returns
+///
+/// A `Result<Response>` containing the updated conversation or an error.
+pub async fn handle_select_alternate(mut req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling POST request to /api/conversation/:topicId/messages/:messageId/alternates");
+
+    let topic_id: String = ctx.param("topicId").map(|s| s.to_string()).unwrap_or_default();
+    let message_id: String = ctx.param("messageId").map(|s| s.to_string()).unwrap_or_default();
+
+    let kv = ctx.kv("DATA_STORE")?;
+    if topics::load_published_topic(&kv, &topic_id).await?.is_none() {
+        return Response::error("Topic not found", 404);
+    }
+
+    let select_request: SelectAlternateRequest = match req.json().await {
+        Ok(request) => request,
+        Err(e) => {
+            console_error!("Error parsing alternate selection: {:?}", e);
+            return Response::error("Invalid JSON input", 400);
+        }
+    };
+
+    let mut conversation = conversation::load_conversation(&kv, &topic_id).await?;
+    if !conversation.messages.iter().any(|msg| msg.id == message_id) {
+        return Response::error("Message not found", 404);
+    }
+    if let Err(e) = conversation::select_alternate(&mut conversation, &message_id, select_request.alternate) {
+        return Response::error(e, 400);
+    }
+
+    conversation::save_conversation(&kv, &conversation).await?;
+    Response::from_json(&conversation)
+}
+
+/// Handles POST request to attach feedback to an assistant message.
+///
+/// Feedback replaces any earlier feedback on the same message.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request containing the feedback
+/// * `ctx` - The route context containing the topic ID and message ID
+///
+/// # Returns
+///
+/// A `Result<Response>` confirming the feedback or an error.
+pub async fn handle_post_feedback(mut req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling POST request to /api/conversation/:topicId/messages/:messageId/feedback");
+
+    let topic_id: String = ctx.param("topicId").map(|s| s.to_string()).unwrap_or_default();
+    let message_id: String = ctx.param("messageId").map(|s| s.to_string()).unwrap_or_default();
+
+    let kv = ctx.kv("DATA_STORE")?;
+    if topics::load_published_topic(&kv, &topic_id).await?.is_none() {
+        return Response::error("Topic not found", 404);
+    }
+
+    let feedback_request: FeedbackRequest = match req.json().await {
+        Ok(feedback) => feedback,
+        Err(e) => {
+            console_error!("Error parsing feedback: {:?}", e);
+            return Response::error("Invalid JSON input", 400);
+        }
+    };
+
+    if feedback_request.comment.as_ref().map_or(0, |comment| comment.chars().count()) > MAX_FEEDBACK_COMMENT_LENGTH {
+        return Response::error(format!("Comment must be at most {} characters", MAX_FEEDBACK_COMMENT_LENGTH), 400);
+    }
+
+    let conversation = conversation::load_conversation(&kv, &topic_id).await?;
+    let message = match conversation.messages.iter().find(|msg| msg.id == message_id) {
+        Some(message) if message.role == "assistant" => message,
+        Some(_) => return Response::error("Feedback can only be given on assistant messages", 400),
+        None => return Response::error("Message not found", 404),
+    };
+
+    let feedback_key = format!("feedback_{}", topic_id);
+    let mut feedback: Vec<MessageFeedback> = kv.get(&feedback_key).json().await?.unwrap_or_default();
+    feedback.retain(|existing| existing.message_id != message_id);
+    feedback.push(MessageFeedback {
+        message_id: message_id.clone(),
+        topic_id: topic_id.clone(),
+        step_id: message.step_id.clone(),
+        rating: feedback_request.rating,
+        category: feedback_request.category,
+        comment: feedback_request.comment.filter(|comment| !comment.trim().is_empty()),
+        message_content: message.content.clone(),
+        created_at: Utc::now(),
+    });
+
+    kv.put(&feedback_key, serde_json::to_string(&feedback)?)?
+        .execute().await?;
+
+    Response::from_json(&GenericResponse {
+        status: 200,
+        message: format!("Feedback recorded for message {}.", message_id),
+    })
+}
+
+/// Handles GET request to retrieve the learner's preferences.
+///
+/// # Arguments
+///
+/// * `_req` - The incoming request (unused)
+/// * `ctx` - The route context
+///
+/// # Returns
+///
+/// A `Result<Response>` containing a JSON object of the preferences or an error.
+pub async fn handle_get_preferences(_req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling GET request to /api/preferences");
+
+    let kv = ctx.kv("DATA_STORE")?;
+    let preferences: Preferences = kv.get(PREFERENCES_KEY).json().await?.unwrap_or_default();
+
+    Response::from_json(&preferences)
+}
+
+/// Handles POST request to update the learner's preferences.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request containing the preferences
+/// * `ctx` - The route context
+///
+/// # Returns
+///
+/// A `Result<Response>` containing the stored preferences or an error.
+pub async fn handle_post_preferences(mut req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling POST request to /api/preferences");
+
+    let mut preferences: Preferences = match req.json().await {
+        Ok(preferences) => preferences,
+        Err(e) => {
+            console_error!("Error parsing preferences: {:?}", e);
+            return Response::error("Invalid JSON input", 400);
+        }
+    };
+
+    if let Some(locale) = &preferences.locale {
+        match i18n::normalize_locale(locale) {
+            Some(supported) => preferences.locale = Some(supported.to_string()),
+            None => return Response::error(format!("Unsupported locale: {}", locale), 400),
+        }
+    }
+
+    let kv = ctx.kv("DATA_STORE")?;
+    kv.put(PREFERENCES_KEY, serde_json::to_string(&preferences)?)?
+        .execute().await?;
+
+    Response::from_json(&preferences)
+}
+
+/// Loads the topic version a learner's progress is pinned to.
+///
+/// If that version is no longer archived, the progress is migrated to the
+/// current version by the ids of its completed steps. The migrated progress is
+/// not stored; only the endpoints that update progress store it.
+async fn load_pinned_topic(kv: &kv::KvStore, topic: &Topic, progress: &mut Progress) -> Result<Topic> {
+    if let Some(pinned_topic) = topics::load_topic_version(kv, topic, progress.topic_version).await? {
+        return Ok(pinned_topic);
+    }
+
+    console_warn!("Version {} of topic {} is not archived, using progress migrated to version {}", progress.topic_version, topic.id, topic.version);
+    *progress = versioning::migrate_unarchived_progress(progress, topic);
+    Ok(topic.clone())
+}
+
+/// Stores progress migrated by `load_pinned_topic` and rejects the request,
+/// whose step indices refer to the version the progress was pinned to.
+async fn reject_migrated_progress(kv: &kv::KvStore, progress: &Progress) -> Result<Response> {
+    kv.put(&progress.topic_id, serde_json::to_string(progress)?)?
+        .execute().await?;
+    Response::error(MIGRATED_PROGRESS_ERROR, 409)
+}
+
+/// Negotiates the locale for a request.
+///
+/// Uses the `locale` query parameter, then the learner's stored preference,
+/// then the `Accept-Language` header.
+async fn negotiate_request_locale(req: &Request, kv: &kv::KvStore) -> Result<&'static str> {
+    let explicit = req.url()?.query_pairs()
+        .find(|(key, _)| key == "locale")
+        .map(|(_, value)| value.to_string());
+    let preferences: Preferences = kv.get(PREFERENCES_KEY).json().await?.unwrap_or_default();
+    let accept_language = req.headers().get("Accept-Language")?;
+
+    Ok(i18n::negotiate_locale(explicit.as_deref(), preferences.locale.as_deref(), accept_language.as_deref()))
+}
+
+/// Handles GET request listing all topics, including unpublished ones, for instructors.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request carrying the admin credentials
+/// * `ctx` - The route context
+///
+/// # Returns
+///
+/// A `Result<Response>` containing a JSON array of all topics or an error.
+pub async fn handle_admin_get_topics(req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling GET request to /api/admin/topics");
+
+    if !utils::is_admin(&req, &ctx)? {
+        return Response::error("Unauthorized", 401);
+    }
+
+    let kv = ctx.kv("DATA_STORE")?;
+    Response::from_json(&topics::load_all_topics(&kv, true).await?)
+}
+
+/// Handles POST request to create a new topic.
+///
+/// New topics are created unpublished and must be published explicitly.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request containing the topic
+/// * `ctx` - The route context
+///
+/// # Returns
+///
+/// A `Result<Response>` containing the created topic or an error.
+pub async fn handle_admin_create_topic(mut req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling POST request to /api/admin/topics");
+
+    if !utils::is_admin(&req, &ctx)? {
+        return Response::error("Unauthorized", 401);
+    }
+
+    let mut topic: Topic = match req.json().await {
+        Ok(topic) => topic,
+        Err(e) => {
+            console_error!("Error parsing topic: {:?}", e);
+            return Response::error("Invalid JSON input", 400);
+        }
+    };
+
+    versioning::assign_step_ids(&mut topic);
+
+    let kv = ctx.kv("DATA_STORE")?;
+    let known_topic_ids = topics::load_known_topic_ids(&kv).await?;
+    if let Err(report) = validation::validate_topic(&topic, &known_topic_ids) {
+        return validation_error_response(report);
+    }
+
+    if topics::load_topic(&kv, &topic.id).await?.is_some() {
+        return Response::error("Topic already exists", 409);
+    }
+
+    topic.version = 1;
+    topic.last_updated = Utc::now();
+    topic.published = false;
+    topics::save_topic(&kv, &topic).await?;
+
+    console_log!("Created topic {}", topic.id);
+    Ok(Response::from_json(&topic)?.with_status(201))
+}
+
+/// Handles PUT request to replace the content of an existing topic.
+///
+/// The publication state of the topic is preserved and a new version is created.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request containing the updated topic
+/// * `ctx` - The route context containing the topic ID
+///
+/// # Returns
+///
+/// A `Result<Response>` containing the updated topic or an error.
+pub async fn handle_admin_update_topic(mut req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling PUT request to /api/admin/topics/:topicId");
+
+    if !utils::is_admin(&req, &ctx)? {
+        return Response::error("Unauthorized", 401);
+    }
+
+    let topic_id: String = ctx.param("topicId").map(|s| s.to_string()).unwrap_or_default();
+
+    let mut topic: Topic = match req.json().await {
+        Ok(topic) => topic,
+        Err(e) => {
+            console_error!("Error parsing topic: {:?}", e);
+            return Response::error("Invalid JSON input", 400);
+        }
+    };
+
+    if topic.id != topic_id {
+        return Response::error("Topic ID in body does not match the URL", 400);
+    }
+    versioning::assign_step_ids(&mut topic);
+
+    let kv = ctx.kv("DATA_STORE")?;
+    let known_topic_ids = topics::load_known_topic_ids(&kv).await?;
+    if let Err(report) = validation::validate_topic(&topic, &known_topic_ids) {
+        return validation_error_response(report);
+    }
+
+    let existing = match topics::load_topic(&kv, &topic_id).await? {
+        Some(existing) => existing,
+        None => return Response::error("Topic not found", 404),
+    };
+
+    topic.published = existing.published;
+    topics::save_topic_revision(&kv, &existing, &mut topic).await?;
+
+    console_log!("Updated topic {}", topic.id);
+    Response::from_json(&topic)
+}
+
+/// Handles DELETE request to remove an authored topic.
+///
+/// Built-in topics cannot be deleted; deleting an authored copy of a built-in
+/// topic restores the built-in content.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request carrying the admin credentials
+/// * `ctx` - The route context containing the topic ID
+///
+/// # Returns
+///
+/// A `Result<Response>` confirming the deletion or an error.
+pub async fn handle_admin_delete_topic(req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling DELETE request to /api/admin/topics/:topicId");
+
+    if !utils::is_admin(&req, &ctx)? {
+        return Response::error("Unauthorized", 401);
+    }
+
+    let topic_id: String = ctx.param("topicId").map(|s| s.to_string()).unwrap_or_default();
+    let kv = ctx.kv("DATA_STORE")?;
+
+    if !topics::delete_topic(&kv, &topic_id).await? {
+        if topics::get_builtin_topic(&topic_id).is_some() {
+            return Response::error("Built-in topics cannot be deleted", 400);
+        }
+        return Response::error("Topic not found", 404);
+    }
+
+    Response::from_json(&GenericResponse {
+        status: 200,
+        message: format!("Topic {} deleted.", topic_id),
+    })
+}
+
+/// Handles POST request to publish a topic.
+pub async fn handle_admin_publish_topic(req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling POST request to /api/admin/topics/:topicId/publish");
+    set_topic_published(req, ctx, true).await
+}
+
+/// Handles POST request to unpublish a topic.
+pub async fn handle_admin_unpublish_topic(req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling POST request to /api/admin/topics/:topicId/unpublish");
+    set_topic_published(req, ctx, false).await
+}
+
+/// Sets the publication state of a topic.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request carrying the admin credentials
+/// * `ctx` - The route context containing the topic ID
+/// * `published` - Whether the topic should be visible to learners
+///
+/// # Returns
+///
+/// A `Result<Response>` containing the updated topic or an error.
+async fn set_topic_published(req: Request, ctx: RouteContext<()>, published: bool) -> Result<Response> {
+    if !utils::is_admin(&req, &ctx)? {
+        return Response::error("Unauthorized", 401);
+    }
+
+    let topic_id: String = ctx.param("topicId").map(|s| s.to_string()).unwrap_or_default();
+    let kv = ctx.kv("DATA_STORE")?;
+
+    let mut topic = match topics::load_topic(&kv, &topic_id).await? {
+        Some(topic) => topic,
+        None => return Response::error("Topic not found", 404),
+    };
+
+    topic.published = published;
+    topic.last_updated = Utc::now();
+    topics::save_topic(&kv, &topic).await?;
+
+    Response::from_json(&topic)
+}
+
+/// Handles POST request to add a step to a topic.
+///
+/// The step is appended unless a `position` query parameter gives the index to insert it at.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request containing the step
+/// * `ctx` - The route context containing the topic ID
+///
+/// # Returns
+///
+/// A `Result<Response>` containing the updated topic or an error.
+pub async fn handle_admin_add_step(mut req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling POST request to /api/admin/topics/:topicId/steps");
+
+    if !utils::is_admin(&req, &ctx)? {
+        return Response::error("Unauthorized", 401);
+    }
+
+    let topic_id: String = ctx.param("topicId").map(|s| s.to_string()).unwrap_or_default();
+    let position = req.url()?.query_pairs()
+        .find(|(key, _)| key == "position")
+        .map(|(_, value)| value.parse::<usize>());
+
+    let step: Step = match req.json().await {
+        Ok(step) => step,
+        Err(e) => {
+            console_error!("Error parsing step: {:?}", e);
+            return Response::error("Invalid JSON input", 400);
+        }
+    };
+
+    let kv = ctx.kv("DATA_STORE")?;
+    let existing = match topics::load_topic(&kv, &topic_id).await? {
+        Some(topic) => topic,
+        None => return Response::error("Topic not found", 404),
+    };
+
+    let mut topic = existing.clone();
+    match position {
+        None => topic.steps.push(step),
+        Some(Ok(index)) if index <= topic.steps.len() => topic.steps.insert(index, step),
+        Some(_) => return Response::error("Invalid step position", 400),
+    }
+
+    versioning::assign_step_ids(&mut topic);
+    let known_topic_ids = topics::load_known_topic_ids(&kv).await?;
+    if let Err(report) = validation::validate_topic(&topic, &known_topic_ids) {
+        return validation_error_response(report);
+    }
+    topics::save_topic_revision(&kv, &existing, &mut topic).await?;
+
+    Ok(Response::from_json(&topic)?.with_status(201))
+}
+
+/// Handles PUT request to replace a step of a topic.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request containing the updated step
+/// * `ctx` - The route context containing the topic ID and step index
+///
+/// # Returns
+///
+/// A `Result<Response>` containing the updated topic or an error.
+pub async fn handle_admin_update_step(mut req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling PUT request to /api/admin/topics/:topicId/steps/:stepIndex");
+
+    if !utils::is_admin(&req, &ctx)? {
+        return Response::error("Unauthorized", 401);
+    }
+
+    let topic_id: String = ctx.param("topicId").map(|s| s.to_string()).unwrap_or_default();
+    let step_index: Option<usize> = ctx.param("stepIndex").and_then(|s| s.parse().ok());
+
+    let step: Step = match req.json().await {
+        Ok(step) => step,
+        Err(e) => {
+            console_error!("Error parsing step: {:?}", e);
+            return Response::error("Invalid JSON input", 400);
+        }
+    };
+
+    let kv = ctx.kv("DATA_STORE")?;
+    let existing = match topics::load_topic(&kv, &topic_id).await? {
+        Some(topic) => topic,
+        None => return Response::error("Topic not found", 404),
+    };
+
+    let mut topic = existing.clone();
+    match step_index.and_then(|index| topic.steps.get_mut(index)) {
+        Some(current) => {
+            let id = if step.id.is_empty() { current.id.clone() } else { step.id.clone() };
+            *current = Step { id, ..step };
+        }
+        None => return Response::error("Step not found", 404),
+    }
+
+    let known_topic_ids = topics::load_known_topic_ids(&kv).await?;
+    if let Err(report) = validation::validate_topic(&topic, &known_topic_ids) {
+        return validation_error_response(report);
+    }
+    topics::save_topic_revision(&kv, &existing, &mut topic).await?;
+
+    Response::from_json(&topic)
+}
+
+/// Handles DELETE request to remove a step from a topic.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request carrying the admin credentials
+/// * `ctx` - The route context containing the topic ID and step index
+///
+/// # Returns
+///
+/// A `Result<Response>` containing the updated topic or an error.
+pub async fn handle_admin_delete_step(req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling DELETE request to /api/admin/topics/:topicId/steps/:stepIndex");
+
+    if !utils::is_admin(&req, &ctx)? {
+        return Response::error("Unauthorized", 401);
+    }
+
+    let topic_id: String = ctx.param("topicId").map(|s| s.to_string()).unwrap_or_default();
+    let step_index: Option<usize> = ctx.param("stepIndex").and_then(|s| s.parse().ok());
+
+    let kv = ctx.kv("DATA_STORE")?;
+    let existing = match topics::load_topic(&kv, &topic_id).await? {
+        Some(topic) => topic,
+        None => return Response::error("Topic not found", 404),
+    };
+
+    let mut topic = existing.clone();
+    match step_index {
+        Some(index) if index < topic.steps.len() => {
+            topic.steps.remove(index);
+        }
+        _ => return Response::error("Step not found", 404),
+    }
+
+    let known_topic_ids = topics::load_known_topic_ids(&kv).await?;
+    if let Err(report) = validation::validate_topic(&topic, &known_topic_ids) {
+        return validation_error_response(report);
+    }
+    topics::save_topic_revision(&kv, &existing, &mut topic).await?;
+
+    Response::from_json(&topic)
+}
+
+/// Handles GET request listing low-rated assistant messages of a topic for instructors.
+///
+/// An optional `step` query parameter restricts the list to a single step ID.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request carrying the admin credentials
+/// * `ctx` - The route context containing the topic ID
+///
+/// # Returns
+///
+/// A `Result<Response>` containing a JSON array of feedback, newest first, or an error.
+pub async fn handle_admin_get_feedback(req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling GET request to /api/admin/feedback/:topicId");
+
+    if !utils::is_admin(&req, &ctx)? {
+        return Response::error("Unauthorized", 401);
+    }
+
+    let topic_id: String = ctx.param("topicId").map(|s| s.to_string()).unwrap_or_default();
+    let step_id = req.url()?.query_pairs()
+        .find(|(key, _)| key == "step")
+        .map(|(_, value)| value.to_string());
+
+    let kv = ctx.kv("DATA_STORE")?;
+    let mut feedback: Vec<MessageFeedback> = kv.get(&format!("feedback_{}", topic_id)).json().await?.unwrap_or_default();
+
+    feedback.retain(|entry| entry.rating == Rating::Down);
+    if let Some(step_id) = &step_id {
+        feedback.retain(|entry| entry.step_id.as_ref() == Some(step_id));
+    }
+    feedback.sort_by_key(|entry| std::cmp::Reverse(entry.created_at));
+
+    Response::from_json(&feedback)
+}
+
+/// Handles GET request listing the documents ingested into the retrieval corpus.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request carrying the admin credentials
+/// * `ctx` - The route context used to access the data store
+///
+/// # Returns
+///
+/// A `Result<Response>` containing a JSON array of the ingested documents or an error.
+pub async fn handle_admin_get_docs(req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling GET request to /api/admin/docs");
+
+    if !utils::is_admin(&req, &ctx)? {
+        return Response::error("Unauthorized", 401);
+    }
+
+    let kv = ctx.kv("DATA_STORE")?;
+    Response::from_json(&rag::load_ingested_documents(&kv).await?)
+}
+
+/// Handles POST request to ingest a markdown document into the retrieval corpus.
+///
+/// A document with the same ID, including a built-in one, is replaced. The
+/// index of the ingested documents is rebuilt.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request containing the document
+/// * `ctx` - The route context used to access the data store
+///
+/// # Returns
+///
+/// A `Result<Response>` confirming the ingestion or an error.
+pub async fn handle_admin_ingest_doc(mut req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling POST request to /api/admin/docs");
+
+    if !utils::is_admin(&req, &ctx)? {
+        return Response::error("Unauthorized", 401);
+    }
+
+    let document: SourceDocument = match req.json().await {
+        Ok(document) => document,
+        Err(e) => {
+            console_error!("Error parsing document: {:?}", e);
+            return Response::error("Invalid JSON input", 400);
+        }
+    };
+
+    let problems = rag::validate_document(&document);
+    if !problems.is_empty() {
+        return Ok(Response::from_json(&ValidationErrorResponse {
+            status: 422,
+            message: format!("Document {} failed validation", document.id),
+            errors: problems,
+        })?.with_status(422));
+    }
+
+    let kv = ctx.kv("DATA_STORE")?;
+    let mut documents = rag::load_ingested_documents(&kv).await?;
+    documents.retain(|existing| existing.id != document.id);
+    let document_id = document.id.clone();
+    documents.push(document);
+    rag::save_ingested_documents(&kv, &documents).await?;
+
+    Response::from_json(&GenericResponse {
+        status: 200,
+        message: format!("Document {} ingested.", document_id),
+    })
+}
+
+/// Handles DELETE request to remove an ingested document from the retrieval corpus.
+///
+/// Removing a document that replaced a built-in one restores the built-in document.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request carrying the admin credentials
+/// * `ctx` - The route context containing the document ID
+///
+/// # Returns
+///
+/// A `Result<Response>` confirming the removal or an error.
+pub async fn handle_admin_delete_doc(req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling DELETE request to /api/admin/docs/:docId");
+
+    if !utils::is_admin(&req, &ctx)? {
+        return Response::error("Unauthorized", 401);
+    }
+
+    let document_id: String = ctx.param("docId").map(|s| s.to_string()).unwrap_or_default();
+
+    let kv = ctx.kv("DATA_STORE")?;
+    let mut documents = rag::load_ingested_documents(&kv).await?;
+    let count = documents.len();
+    documents.retain(|existing| existing.id != document_id);
+    if documents.len() == count {
+        return Response::error("Document not found", 404);
+    }
+    rag::save_ingested_documents(&kv, &documents).await?;
+
+    Response::from_json(&GenericResponse {
+        status: 200,
+        message: format!("Document {} removed.", document_id),
+    })
+}
+
+/// Handles GET request for the guardrail policy.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request carrying the admin credentials
+/// * `ctx` - The route context used to access the data store
+///
+/// # Returns
+///
+/// A `Result<Response>` containing the policy or an error.
+pub async fn handle_admin_get_guardrail_policy(req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling GET request to /api/admin/guardrails/policy");
+
+    if !utils::is_admin(&req, &ctx)? {
+        return Response::error("Unauthorized", 401);
+    }
+
+    let kv = ctx.kv("DATA_STORE")?;
+    Response::from_json(&guardrails::load_policy(&kv).await?)
+}
+
+/// Handles PUT request to replace the guardrail policy.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request containing the policy
+/// * `ctx` - The route context used to access the data store
+///
+/// # Returns
+///
+/// A `Result<Response>` containing the stored policy or an error.
+pub async fn handle_admin_update_guardrail_policy(mut req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling PUT request to /api/admin/guardrails/policy");
+
+    if !utils::is_admin(&req, &ctx)? {
+        return Response::error("Unauthorized", 401);
+    }
+
+    let policy: GuardrailPolicy = match req.json().await {
+        Ok(policy) => policy,
+        Err(e) => {
+            console_error!("Error parsing guardrail policy: {:?}", e);
+            return Response::error("Invalid JSON input", 400);
+        }
+    };
+
+    let problems = guardrails::validate_policy(&policy);
+    if !problems.is_empty() {
+        return Ok(Response::from_json(&ValidationErrorResponse {
+            status: 422,
+            message: "Guardrail policy failed validation".to_string(),
+            errors: problems,
+        })?.with_status(422));
+    }
+
+    let kv = ctx.kv("DATA_STORE")?;
+    guardrails::save_policy(&kv, &policy).await?;
+    Response::from_json(&policy)
+}
+
+/// Handles GET request for the guardrail audit log.
+///
+/// An optional `rule` query parameter restricts the log to a single rule ID.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request carrying the admin credentials
+/// * `ctx` - The route context used to access the data store
+///
+/// # Returns
+///
+/// A `Result<Response>` containing a JSON array of audit entries, newest first, or an error.
+pub async fn handle_admin_get_guardrail_audit(req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling GET request to /api/admin/guardrails/audit");
+
+    if !utils::is_admin(&req, &ctx)? {
+        return Response::error("Unauthorized", 401);
+    }
+
+    let rule_id = req.url()?.query_pairs()
+        .find(|(key, _)| key == "rule")
+        .map(|(_, value)| value.to_string());
+
+    let kv = ctx.kv("DATA_STORE")?;
+    let mut log = guardrails::load_audit_log(&kv).await?;
+    if let Some(rule_id) = &rule_id {
+        log.retain(|entry| &entry.rule_id == rule_id);
+    }
+    log.reverse();
+
+    Response::from_json(&log)
+}
+
+/// Handles GET request for the prompt templates.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request carrying the admin credentials
+/// * `ctx` - The route context
+///
+/// # Returns
+///
+/// A `Result<Response>` containing a JSON array of every template version.
+pub async fn handle_admin_get_prompts(req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling GET request to /api/admin/prompts");
+
+    if !utils::is_admin(&req, &ctx)? {
+        return Response::error("Unauthorized", 401);
+    }
+
+    Response::from_json(&prompts::builtin_templates())
+}
+
+/// Handles GET request for the experiment running for a prompt template.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request carrying the admin credentials
+/// * `ctx` - The route context containing the template name
+///
+/// # Returns
+///
+/// A `Result<Response>` containing the experiment, or a 404 error if none is running.
+pub async fn handle_admin_get_prompt_experiment(req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling GET request to /api/admin/prompts/:name/experiment");
+
+    if !utils::is_admin(&req, &ctx)? {
+        return Response::error("Unauthorized", 401);
+    }
+
+    let name: String = ctx.param("name").map(|s| s.to_string()).unwrap_or_default();
+    let kv = ctx.kv("DATA_STORE")?;
+    match prompts::load_experiment(&kv, &name).await? {
+        Some(experiment) => Response::from_json(&experiment),
+        None => Response::error("No prompt experiment is running for this template", 404),
+    }
+}
+
+/// Handles PUT request to start an experiment for a prompt template, replacing
+/// the one running for it. Experiments on other templates keep running.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request containing the experiment
+/// * `ctx` - The route context containing the template name
+///
+/// # Returns
+///
+/// A `Result<Response>` containing the stored experiment or an error.
+pub async fn handle_admin_update_prompt_experiment(mut req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling PUT request to /api/admin/prompts/:name/experiment");
+
+    if !utils::is_admin(&req, &ctx)? {
+        return Response::error("Unauthorized", 401);
+    }
+
+    let name: String = ctx.param("name").map(|s| s.to_string()).unwrap_or_default();
+    let experiment: PromptExperiment = match req.json().await {
+        Ok(experiment) => experiment,
+        Err(e) => {
+            console_error!("Error parsing prompt experiment: {:?}", e);
+            return Response::error("Invalid JSON input", 400);
+        }
+    };
+
+    let mut problems = prompts::validate_experiment(&experiment);
+    if experiment.template != name {
+        problems.insert(0, format!("template: must match the template '{}' in the path", name));
+    }
+    if !problems.is_empty() {
+        return Ok(Response::from_json(&ValidationErrorResponse {
+            status: 422,
+            message: "Prompt experiment failed validation".to_string(),
+            errors: problems,
+        })?.with_status(422));
+    }
+
+    let kv = ctx.kv("DATA_STORE")?;
+    prompts::save_experiment(&kv, &experiment).await?;
+    Response::from_json(&experiment)
+}
+
+/// Handles DELETE request to end the experiment running for a prompt template.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request carrying the admin credentials
+/// * `ctx` - The route context containing the template name
+///
+/// # Returns
+///
+/// A `Result<Response>` confirming the experiment ended or an error.
+pub async fn handle_admin_delete_prompt_experiment(req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling DELETE request to /api/admin/prompts/:name/experiment");
+
+    if !utils::is_admin(&req, &ctx)? {
+        return Response::error("Unauthorized", 401);
+    }
+
+    let name: String = ctx.param("name").map(|s| s.to_string()).unwrap_or_default();
+    let kv = ctx.kv("DATA_STORE")?;
+    prompts::delete_experiment(&kv, &name).await?;
+
+    Response::from_json(&GenericResponse {
+        status: 200,
+        message: format!("Prompt experiment ended. Learners now use version {} of {}.", prompts::DEFAULT_VERSION, name),
+    })
+}
+
+/// Handles GET request for the tokens used by calls to the Claude API.
+///
+/// An optional `date` query parameter (YYYY-MM-DD, UTC) selects the day; it
+/// defaults to today.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request carrying the admin credentials
+/// * `ctx` - The route context used to access the data store
+///
+/// # Returns
+///
+/// A `Result<Response>` containing the usage of the day by purpose, or an error.
+pub async fn handle_admin_get_usage(req: Request, ctx: RouteContext<()>) -> Result<Response> {
+    console_log!("Handling GET request to /api/admin/usage");
+
+    if !utils::is_admin(&req, &ctx)? {
+        return Response::error("Unauthorized", 401);
+    }
+
+    let date = match req.url()?.query_pairs().find(|(key, _)| key == "date") {
+        Some((_, value)) => match NaiveDate::parse_from_str(&value, "%Y-%m-%d") {
+            Ok(date) => date,
+            Err(_) => return Response::error("date must be formatted as YYYY-MM-DD", 400),
+        },
+        None => Utc::now().date_naive(),
+    };
+
+    let kv = ctx.kv("DATA_STORE")?;
+    Response::from_json(&usage::load_usage(&kv, date).await?)
+}
+
+/// Builds a 422 response listing the validation errors of a topic.
+fn validation_error_response(report: ValidationReport) -> Result<Response> {
+    Ok(Response::from_json(&ValidationErrorResponse {
+        status: 422,
+        message: format!("Topic {} failed validation", report.topic_id),
+        errors: report.messages(),
+    })?.with_status(422))
 }
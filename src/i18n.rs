@@ -0,0 +1,158 @@
+//! This module handles localization of topic content and locale negotiation.
+//!
+//! Topic text is authored in English, with optional translations keyed by locale.
+//! Any text missing from a translation falls back to English.
+
+use crate::types::Topic;
+
+/// The locale topic content is authored in.
+pub const DEFAULT_LOCALE: &str = "en";
+
+/// Locales supported by the platform, with the language name given to the model.
+pub const SUPPORTED_LOCALES: &[(&str, &str)] = &[
+    ("en", "English"),
+    ("es", "Spanish"),
+    ("fr", "French"),
+    ("de", "German"),
+    ("pt", "Portuguese"),
+    ("ja", "Japanese"),
+];
+
+/// Returns the supported locale matching a language tag, if any.
+///
+/// Tags are matched case-insensitively on their primary language subtag,
+/// so `pt-BR` resolves to `pt`.
+pub fn normalize_locale(tag: &str) -> Option<&'static str> {
+    let primary = tag.trim().split(['-', '_']).next()?.to_lowercase();
+    SUPPORTED_LOCALES.iter()
+        .map(|(locale, _)| *locale)
+        .find(|locale| *locale == primary)
+}
+
+/// Returns the English name of a supported locale's language.
+pub fn language_name(locale: &str) -> &'static str {
+    SUPPORTED_LOCALES.iter()
+        .find(|(code, _)| *code == locale)
+        .map(|(_, name)| *name)
+        .unwrap_or("English")
+}
+
+/// Negotiates the locale to use for a request.
+///
+/// An explicit locale takes precedence over the learner's stored preference,
+/// which takes precedence over the `Accept-Language` header. Unsupported
+/// locales are skipped and English is used if nothing matches.
+///
+/// # Arguments
+///
+/// * `explicit` - A locale requested explicitly, e.g. via a query parameter
+/// * `preference` - The learner's stored locale preference
+/// * `accept_language` - The value of the `Accept-Language` header
+pub fn negotiate_locale(explicit: Option<&str>, preference: Option<&str>, accept_language: Option<&str>) -> &'static str {
+    if let Some(locale) = explicit.and_then(normalize_locale).or_else(|| preference.and_then(normalize_locale)) {
+        return locale;
+    }
+
+    let mut candidates: Vec<(&str, f32)> = accept_language.unwrap_or("")
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.split(';');
+            let tag = parts.next()?.trim();
+            let quality = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if tag.is_empty() || quality <= 0.0 { None } else { Some((tag, quality)) }
+        })
+        .collect();
+    // Stable sort keeps the header order for equal quality values
+    candidates.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+
+    candidates.into_iter()
+        .find_map(|(tag, _)| normalize_locale(tag))
+        .unwrap_or(DEFAULT_LOCALE)
+}
+
+/// Returns a copy of the topic with its text in the given locale.
+///
+/// Fields without a translation keep their English text. The translations
+/// themselves are removed from the returned topic.
+pub fn localize_topic(topic: &Topic, locale: &str) -> Topic {
+    let mut localized = topic.clone();
+    localized.translations.clear();
+
+    let translation = match topic.translations.get(locale) {
+        Some(translation) => translation,
+        None => return localized,
+    };
+
+    if let Some(title) = &translation.title {
+        localized.title = title.clone();
+    }
+    if let Some(description) = &translation.description {
+        localized.description = description.clone();
+    }
+    if let Some(initial_message) = &translation.initial_message {
+        localized.initial_message = initial_message.clone();
+    }
+
+    for step in localized.steps.iter_mut() {
+        let step_translation = match translation.steps.get(&step.id) {
+            Some(step_translation) => step_translation,
+            None => continue,
+        };
+        if let Some(title) = &step_translation.title {
+            step.title = title.clone();
+        }
+        if let Some(prompt) = &step_translation.prompt {
+            step.prompt = prompt.clone();
+        }
+        if let Some(questions) = &step_translation.suggested_questions {
+            step.suggested_questions = questions.clone();
+        }
+    }
+
+    localized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::topics::get_github_setup_topic;
+    use crate::types::{StepTranslation, TopicTranslation};
+
+    #[test]
+    fn test_negotiate_locale() {
+        assert_eq!(negotiate_locale(None, None, None), "en");
+        assert_eq!(negotiate_locale(None, None, Some("pt-BR,pt;q=0.9,en;q=0.8")), "pt");
+        assert_eq!(negotiate_locale(None, None, Some("ko;q=0.9, fr;q=0.5, de;q=0.7")), "de");
+        assert_eq!(negotiate_locale(None, Some("es"), Some("fr")), "es");
+        assert_eq!(negotiate_locale(Some("ja"), Some("es"), Some("fr")), "ja");
+        assert_eq!(negotiate_locale(Some("xx"), None, Some("fr-CA")), "fr");
+    }
+
+    #[test]
+    fn test_localize_topic_falls_back_to_english() {
+        let mut topic = get_github_setup_topic();
+        let mut translation = TopicTranslation {
+            title: Some("Configuración de GitHub".to_string()),
+            ..Default::default()
+        };
+        translation.steps.insert("create-account".to_string(), StepTranslation {
+            title: Some("Crea una cuenta de GitHub".to_string()),
+            ..Default::default()
+        });
+        topic.translations.insert("es".to_string(), translation);
+
+        let localized = localize_topic(&topic, "es");
+
+        assert_eq!(localized.title, "Configuración de GitHub");
+        assert_eq!(localized.description, topic.description);
+        assert_eq!(localized.steps[1].title, "Crea una cuenta de GitHub");
+        assert_eq!(localized.steps[1].prompt, topic.steps[1].prompt);
+        assert_eq!(localized.steps[0].title, topic.steps[0].title);
+        assert!(localized.translations.is_empty());
+
+        assert_eq!(localize_topic(&topic, "fr").title, topic.title);
+    }
+}
@@ -3,11 +3,30 @@
 
 use worker::*;
 
-mod types;
+pub mod types;
 mod handlers;
+mod i18n;
+mod prompts;
+mod quiz;
+mod rag;
+mod redaction;
+mod search;
+mod attachments;
 mod claude;
+mod conversation;
+pub mod eval;
+mod export;
+mod fixtures;
+mod followups;
+mod framing;
+mod grading;
+mod guardrails;
+mod tools;
+mod usage;
 mod utils;
-mod topics;
+pub mod topics;
+pub mod validation;
+mod versioning;
 
 /// The main entry point for the Worker.
 ///
@@ -44,9 +63,44 @@ pub async fn main(req: Request, env: Env, _ctx: Context) -> Result<Response> {
         .get_async("/api/topics/:topicId", handlers::handle_get_topic)
         .post_async("/api/progress/:topicId", handlers::handle_post_progress)
         .get_async("/api/progress/:topicId", handlers::handle_get_progress)
+        .post_async("/api/progress/:topicId/migrate", handlers::handle_migrate_progress)
+        .post_async("/api/quiz/:topicId/:stepIndex", handlers::handle_post_quiz)
+        .post_async("/api/exercise/:topicId/:stepIndex", handlers::handle_post_exercise)
         .post_async("/api/chat/:topicId", handlers::handle_post_chat)
+        .post_async("/api/chat/:topicId/regenerate", handlers::handle_regenerate_chat)
+        .post_async("/api/chat/:topicId/edit", handlers::handle_edit_chat)
         .get_async("/api/conversation/:topicId", handlers::handle_get_conversation)
+        .get_async("/api/conversation/:topicId/export", handlers::handle_export_conversation)
+        .post_async("/api/conversation/:topicId/import", handlers::handle_import_conversation)
+        .get_async("/api/conversation/:topicId/threads", handlers::handle_get_threads)
+        .get_async("/api/conversation/:topicId/threads/:threadId", handlers::handle_get_thread)
+        .post_async("/api/conversation/:topicId/messages/:messageId/feedback", handlers::handle_post_feedback)
+        .post_async("/api/conversation/:topicId/messages/:messageId/alternates", handlers::handle_select_alternate)
+        .get_async("/api/conversations/search", handlers::handle_search_conversations)
         .post_async("/api/reset/:topicId", handlers::handle_reset_progress)
+        .get_async("/api/preferences", handlers::handle_get_preferences)
+        .post_async("/api/preferences", handlers::handle_post_preferences)
+        .get_async("/api/admin/topics", handlers::handle_admin_get_topics)
+        .post_async("/api/admin/topics", handlers::handle_admin_create_topic)
+        .put_async("/api/admin/topics/:topicId", handlers::handle_admin_update_topic)
+        .delete_async("/api/admin/topics/:topicId", handlers::handle_admin_delete_topic)
+        .post_async("/api/admin/topics/:topicId/publish", handlers::handle_admin_publish_topic)
+        .post_async("/api/admin/topics/:topicId/unpublish", handlers::handle_admin_unpublish_topic)
+        .post_async("/api/admin/topics/:topicId/steps", handlers::handle_admin_add_step)
+        .put_async("/api/admin/topics/:topicId/steps/:stepIndex", handlers::handle_admin_update_step)
+        .delete_async("/api/admin/topics/:topicId/steps/:stepIndex", handlers::handle_admin_delete_step)
+        .get_async("/api/admin/feedback/:topicId", handlers::handle_admin_get_feedback)
+        .get_async("/api/admin/docs", handlers::handle_admin_get_docs)
+        .post_async("/api/admin/docs", handlers::handle_admin_ingest_doc)
+        .delete_async("/api/admin/docs/:docId", handlers::handle_admin_delete_doc)
+        .get_async("/api/admin/guardrails/policy", handlers::handle_admin_get_guardrail_policy)
+        .put_async("/api/admin/guardrails/policy", handlers::handle_admin_update_guardrail_policy)
+        .get_async("/api/admin/guardrails/audit", handlers::handle_admin_get_guardrail_audit)
+        .get_async("/api/admin/prompts", handlers::handle_admin_get_prompts)
+        .get_async("/api/admin/prompts/:name/experiment", handlers::handle_admin_get_prompt_experiment)
+        .put_async("/api/admin/prompts/:name/experiment", handlers::handle_admin_update_prompt_experiment)
+        .delete_async("/api/admin/prompts/:name/experiment", handlers::handle_admin_delete_prompt_experiment)
+        .get_async("/api/admin/usage", handlers::handle_admin_get_usage)
         .run(req, env)
         .await
         .map(|mut res| {
@@ -0,0 +1,440 @@
+//! This module contains the prompt templates sent to the model.
+//!
+//! Prompts are named, versioned templates stored under `src/prompts/`. They are
+//! rendered by a small template engine supporting `{{variable}}` placeholders
+//! and `{{#if variable}} ... {{/if}}` blocks, which are kept when the variable
+//! is not empty. Admins can run an A/B experiment per template, assigning each
+//! learner to a version of it; the version used is stored on each reply.
+
+use std::collections::HashMap;
+use chrono::Utc;
+use worker::*;
+use worker::wasm_bindgen::JsCast;
+use crate::i18n;
+use crate::types::{Difficulty, PromptAssignment, PromptExperiment, PromptTemplate, Progress, Step, Topic};
+
+/// Name of the template of the tutor's system prompt.
+pub const TUTOR_SYSTEM: &str = "tutor-system";
+/// Name of the template of the exercise grading system prompt.
+pub const EXERCISE_GRADING: &str = "exercise-grading";
+
+/// Version of a template used when no experiment is running for it.
+pub const DEFAULT_VERSION: u32 = 1;
+
+/// Returns the KV key of the experiment running for a template.
+fn experiment_key(template: &str) -> String {
+    format!("prompt_experiment_{}", template)
+}
+
+/// Returns the KV key of a learner's assignment to a version of a template.
+fn assignment_key(template: &str, learner_id: &str) -> String {
+    format!("prompt_assignment_{}_{}", template, learner_id)
+}
+
+/// The built-in templates, as (name, version, description, body).
+///
+/// Replies record the version they were generated with, so a published body is
+/// never edited; changes are made in a new version.
+const TEMPLATES: &[(&str, u32, &str, &str)] = &[
+    (
+        TUTOR_SYSTEM,
+        1,
+        "Guideline-based tutor prompt.",
+        include_str!("prompts/tutor_system_v1.txt"),
+    ),
+    (
+        TUTOR_SYSTEM,
+        2,
+        "Concise tutor prompt focused on the current step, with one worked example and a next thing to try.",
+        include_str!("prompts/tutor_system_v2.txt"),
+    ),
+    (
+        EXERCISE_GRADING,
+        1,
+        "Rubric-based grading prompt returning a JSON verdict.",
+        include_str!("prompts/exercise_grading_v1.txt"),
+    ),
+];
+
+/// Returns every built-in template, grouped by name and ordered by version.
+pub fn builtin_templates() -> Vec<PromptTemplate> {
+    TEMPLATES.iter()
+        .map(|(name, version, description, body)| PromptTemplate {
+            name: name.to_string(),
+            version: *version,
+            description: description.to_string(),
+            body: body.to_string(),
+        })
+        .collect()
+}
+
+/// Returns a version of a built-in template, if it exists.
+pub fn find_template(name: &str, version: u32) -> Option<PromptTemplate> {
+    builtin_templates().into_iter()
+        .find(|template| template.name == name && template.version == version)
+}
+
+/// Returns the label identifying a template version, e.g. `tutor-system@2`.
+pub fn version_label(template: &PromptTemplate) -> String {
+    format!("{}@{}", template.name, template.version)
+}
+
+/// Renders a template.
+///
+/// A line holding nothing but an `{{#if}}` or `{{/if}}` tag is removed entirely,
+/// so blocks do not leave blank lines behind. Variable values are inserted as
+/// they are and are never rendered themselves.
+///
+/// # Arguments
+///
+/// * `body` - The body of the template
+/// * `variables` - The values of the variables the template uses
+///
+/// # Returns
+///
+/// The rendered text, or a description of the problem if the template uses an
+/// unknown variable or is malformed.
+pub fn render(body: &str, variables: &HashMap<&str, String>) -> std::result::Result<String, String> {
+    let body: String = body.split_inclusive('\n')
+        .map(|line| if is_block_tag(line.trim()) { line.trim() } else { line })
+        .collect();
+
+    let lookup = |name: &str| -> std::result::Result<&String, String> {
+        variables.get(name).ok_or_else(|| format!("unknown variable '{}'", name))
+    };
+
+    let mut rendered = String::new();
+    // Whether each open block is rendered
+    let mut blocks: Vec<bool> = vec![];
+    let mut rest = body.as_str();
+
+    while let Some(start) = rest.find("{{") {
+        let is_rendered = blocks.iter().all(|&shown| shown);
+        if is_rendered {
+            rendered.push_str(&rest[..start]);
+        }
+        let end = rest[start..].find("}}").ok_or("unclosed '{{'")? + start;
+        let tag = rest[start + 2..end].trim();
+
+        if let Some(name) = tag.strip_prefix("#if ") {
+            blocks.push(!lookup(name.trim())?.trim().is_empty());
+        } else if tag == "/if" {
+            blocks.pop().ok_or("'{{/if}}' without a matching '{{#if}}'")?;
+        } else if tag.is_empty() || tag.contains(char::is_whitespace) {
+            return Err(format!("invalid tag '{{{{{}}}}}'", tag));
+        } else {
+            let value = lookup(tag)?;
+            if is_rendered {
+                rendered.push_str(value);
+            }
+        }
+        rest = &rest[end + 2..];
+    }
+    if !blocks.is_empty() {
+        return Err("unclosed '{{#if}}' block".to_string());
+    }
+    rendered.push_str(rest);
+
+    Ok(rendered.trim_end().to_string())
+}
+
+/// Returns true if a trimmed line is a single block tag.
+fn is_block_tag(line: &str) -> bool {
+    (line.starts_with("{{#if ") || line.starts_with("{{/if"))
+        && line.ends_with("}}")
+        && line.matches("{{").count() == 1
+}
+
+/// Builds the variables of the tutor system prompt.
+///
+/// # Arguments
+///
+/// * `topic` - The learner's pinned topic version, localized
+/// * `step` - The step the learner is on, if any
+/// * `progress` - The learner's progress on the topic
+/// * `locale` - The learner's locale, which the response should be written in
+/// * `framing` - The instruction explaining how learner input is framed
+/// * `follow_ups` - The instruction asking for follow-up questions
+pub fn tutor_variables(
+    topic: &Topic,
+    step: Option<&Step>,
+    progress: &Progress,
+    locale: &str,
+    framing: String,
+    follow_ups: String,
+) -> HashMap<&'static str, String> {
+    // English is the language of the templates, so it needs no instruction
+    let language = if locale == i18n::DEFAULT_LOCALE { "" } else { i18n::language_name(locale) };
+
+    HashMap::from([
+        ("topic", topic.title.clone()),
+        ("step", step.map(|step| step.title.clone()).unwrap_or_default()),
+        ("step_prompt", step.map(|step| step.prompt.clone()).unwrap_or_default()),
+        ("learner_level", learner_level(topic.difficulty).to_string()),
+        ("summary", progress_summary(topic, progress)),
+        ("language", language.to_string()),
+        // Documents are sent with the latest learner message so the system prompt
+        // stays cacheable; published templates keep the variable, left empty
+        ("documents", String::new()),
+        ("framing", framing),
+        ("follow_ups", follow_ups),
+    ])
+}
+
+/// Returns the learner level corresponding to a topic's difficulty.
+fn learner_level(difficulty: Difficulty) -> &'static str {
+    match difficulty {
+        Difficulty::Beginner => "beginner",
+        Difficulty::Intermediate => "intermediate",
+        Difficulty::Advanced => "advanced",
+    }
+}
+
+/// Summarizes the steps a learner has completed, or returns an empty string if none.
+fn progress_summary(topic: &Topic, progress: &Progress) -> String {
+    let completed: Vec<&str> = topic.steps.iter()
+        .enumerate()
+        .filter(|(index, _)| progress.completed_steps.contains(index))
+        .map(|(_, step)| step.title.as_str())
+        .collect();
+    if completed.is_empty() {
+        return String::new();
+    }
+
+    format!("completed {} of {} steps ({}).", completed.len(), topic.steps.len(), completed.join(", "))
+}
+
+/// Loads the experiment running for a template, if any.
+pub async fn load_experiment(kv: &kv::KvStore, template: &str) -> Result<Option<PromptExperiment>> {
+    Ok(kv.get(&experiment_key(template)).json().await?)
+}
+
+/// Starts an experiment, replacing the one running for the same template.
+pub async fn save_experiment(kv: &kv::KvStore, experiment: &PromptExperiment) -> Result<()> {
+    kv.put(&experiment_key(&experiment.template), serde_json::to_string(experiment)?)?
+        .execute().await?;
+    Ok(())
+}
+
+/// Ends the experiment running for a template. Learners go back to the default version.
+pub async fn delete_experiment(kv: &kv::KvStore, template: &str) -> Result<()> {
+    kv.delete(&experiment_key(template)).await?;
+    Ok(())
+}
+
+/// Checks that an experiment can be run.
+///
+/// # Returns
+///
+/// A description of every problem found, empty if the experiment is valid.
+pub fn validate_experiment(experiment: &PromptExperiment) -> Vec<String> {
+    let mut problems = vec![];
+    if !TEMPLATES.iter().any(|(name, ..)| *name == experiment.template) {
+        problems.push(format!("template: unknown template '{}'", experiment.template));
+    }
+    if experiment.variants.is_empty() {
+        problems.push("variants: at least one variant is required".to_string());
+    }
+    for (index, variant) in experiment.variants.iter().enumerate() {
+        if find_template(&experiment.template, variant.version).is_none() {
+            problems.push(format!("variants[{}].version: unknown version {}", index, variant.version));
+        } else if experiment.variants[..index].iter().any(|other| other.version == variant.version) {
+            problems.push(format!("variants[{}].version: duplicate version {}", index, variant.version));
+        }
+    }
+    if !experiment.variants.is_empty() && experiment.variants.iter().all(|variant| variant.weight == 0) {
+        problems.push("variants: at least one variant must have a positive weight".to_string());
+    }
+    problems
+}
+
+/// Picks a variant of an experiment in proportion to the variants' weights.
+///
+/// # Arguments
+///
+/// * `experiment` - The experiment, with at least one positive weight
+/// * `seed` - A random number choosing the variant
+///
+/// # Returns
+///
+/// The version of the chosen variant.
+pub fn assign_variant(experiment: &PromptExperiment, seed: u32) -> u32 {
+    let total: u64 = experiment.variants.iter().map(|variant| u64::from(variant.weight)).sum();
+    if total == 0 {
+        return DEFAULT_VERSION;
+    }
+
+    let mut pick = u64::from(mix_seed(seed)) % total;
+    for variant in &experiment.variants {
+        if pick < u64::from(variant.weight) {
+            return variant.version;
+        }
+        pick -= u64::from(variant.weight);
+    }
+    DEFAULT_VERSION
+}
+
+/// Scrambles the bits of a seed, so that seeds sharing their low digits, such as
+/// timestamps with a coarse resolution, still spread across the variants.
+fn mix_seed(seed: u32) -> u32 {
+    let mut hash = seed;
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85eb_ca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2_ae35);
+    hash ^= hash >> 16;
+    hash
+}
+
+/// Returns a random number from the runtime's cryptographic random number generator.
+fn random_seed() -> Result<u32> {
+    let crypto = js_sys::Reflect::get(&js_sys::global(), &"crypto".into())?;
+    let get_random_values: js_sys::Function = js_sys::Reflect::get(&crypto, &"getRandomValues".into())?.dyn_into()?;
+    let values = js_sys::Uint32Array::new_with_length(1);
+    get_random_values.call1(&crypto, &values)?;
+    Ok(values.get_index(0))
+}
+
+/// Returns the version of a template to use for the learner.
+///
+/// While an experiment runs for the template, the learner keeps the variant
+/// they were assigned to; they are assigned again if their variant left the
+/// experiment. Requests without a learner identifier are assigned a variant
+/// each time. Otherwise the default version is used.
+///
+/// # Arguments
+///
+/// * `kv` - The data store holding experiments and assignments
+/// * `name` - The name of the template
+/// * `learner_id` - The learner's identifier, if the request carried one
+///
+/// # Returns
+///
+/// The template to render.
+pub async fn resolve_template(kv: &kv::KvStore, name: &str, learner_id: Option<&str>) -> Result<PromptTemplate> {
+    let version = match load_experiment(kv, name).await? {
+        Some(experiment) => match learner_id {
+            Some(learner_id) => {
+                let key = assignment_key(name, learner_id);
+                let assignment: Option<PromptAssignment> = kv.get(&key).json().await?;
+                match assignment {
+                    Some(assignment) if experiment.variants.iter().any(|variant| variant.version == assignment.version && variant.weight > 0) => assignment.version,
+                    _ => {
+                        let version = assign_variant(&experiment, random_seed()?);
+                        console_log!("Assigned learner {} to version {} of prompt template {}", learner_id, version, name);
+                        let assignment = PromptAssignment {
+                            template: name.to_string(),
+                            version,
+                            assigned_at: Utc::now(),
+                        };
+                        kv.put(&key, serde_json::to_string(&assignment)?)?
+                            .execute().await?;
+                        version
+                    }
+                }
+            }
+            None => assign_variant(&experiment, random_seed()?),
+        },
+        None => DEFAULT_VERSION,
+    };
+
+    find_template(name, version)
+        .ok_or_else(|| Error::from(format!("Unknown prompt template {}@{}", name, version)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::topics::get_github_setup_topic;
+    use crate::types::PromptVariant;
+
+    #[test]
+    fn test_render() {
+        let variables = HashMap::from([
+            ("topic", "Git".to_string()),
+            ("step", String::new()),
+            ("summary", "{{topic}}".to_string()),
+        ]);
+
+        let rendered = render("Topic: {{ topic }}\n{{#if step}}\nStep: {{step}}\n{{/if}}\n{{#if summary}}\nSummary: {{summary}}\n{{/if}}\nEnd", &variables);
+        assert_eq!(rendered, Ok("Topic: Git\nSummary: {{topic}}\nEnd".to_string()));
+
+        assert_eq!(render("{{level}}", &variables), Err("unknown variable 'level'".to_string()));
+        assert_eq!(render("{{#if step}}never closed", &variables), Err("unclosed '{{#if}}' block".to_string()));
+        assert!(render("{{/if}}", &variables).is_err());
+        assert!(render("{{topic", &variables).is_err());
+    }
+
+    #[test]
+    fn test_builtin_templates_render() {
+        let topic = get_github_setup_topic();
+        let mut progress = Progress::new(&topic.id, topic.version);
+        progress.completed_steps = vec![0];
+
+        for template in builtin_templates().iter().filter(|template| template.name == TUTOR_SYSTEM) {
+            let variables = tutor_variables(&topic, topic.steps.get(1), &progress, "es", "FRAMING".to_string(), "FOLLOW-UPS".to_string());
+            let rendered = render(&template.body, &variables).unwrap();
+
+            assert!(rendered.contains(&topic.title), "{}", version_label(template));
+            assert!(rendered.contains(&topic.steps[1].title));
+            assert!(rendered.contains(&format!("completed 1 of {} steps ({})", topic.steps.len(), topic.steps[0].title)));
+            assert!(rendered.contains("Always respond in Spanish"));
+            assert!(rendered.ends_with("FRAMING\n\n    FOLLOW-UPS"));
+            assert!(!rendered.contains("{{"));
+            assert!(!rendered.contains("\n\n\n"));
+        }
+
+        let grading = find_template(EXERCISE_GRADING, 1).unwrap();
+        let variables = HashMap::from([
+            ("step", "Commit".to_string()),
+            ("instructions", "Explain commits".to_string()),
+            ("rubric", "- a (1 point(s)): mentions snapshots".to_string()),
+            ("language", "English".to_string()),
+        ]);
+        assert!(render(&grading.body, &variables).unwrap().contains("{\"met_criteria\": [\"<criterion id>\", ...]"));
+    }
+
+    #[test]
+    fn test_assign_variant() {
+        let experiment = PromptExperiment {
+            template: TUTOR_SYSTEM.to_string(),
+            variants: vec![
+                PromptVariant { version: 1, weight: 1 },
+                PromptVariant { version: 2, weight: 3 },
+            ],
+        };
+        assert!(validate_experiment(&experiment).is_empty());
+
+        let assigned: Vec<u32> = (0..8).map(|seed| assign_variant(&experiment, seed)).collect();
+        assert_eq!(assigned, vec![1, 2, 2, 2, 2, 2, 1, 1]);
+
+        let invalid = PromptExperiment {
+            template: TUTOR_SYSTEM.to_string(),
+            variants: vec![PromptVariant { version: 3, weight: 0 }],
+        };
+        assert_eq!(validate_experiment(&invalid), vec![
+            "variants[0].version: unknown version 3",
+            "variants: at least one variant must have a positive weight",
+        ]);
+    }
+
+    #[test]
+    fn test_assign_variant_spreads_coarse_seeds() {
+        // Timestamps with millisecond resolution give nanosecond counts that
+        // are all multiples of 1,000,000.
+        for total in [2, 4, 5, 10, 100] {
+            let experiment = PromptExperiment {
+                template: TUTOR_SYSTEM.to_string(),
+                variants: vec![
+                    PromptVariant { version: 1, weight: 1 },
+                    PromptVariant { version: 2, weight: total - 1 },
+                ],
+            };
+
+            let first = (0..1000).filter(|k| assign_variant(&experiment, k * 1_000_000) == 1).count();
+
+            let expected = 1000 / total as usize;
+            assert!(first >= expected / 2 && first <= expected * 2, "total {}: {} of 1000 seeds chose version 1", total, first);
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! This module contains the grading logic for quiz steps.
+//!
+//! Answer keys never leave the server: topics are passed through
+//! `strip_answer_keys` before they are served to learners.
+
+use chrono::Utc;
+use crate::types::{Quiz, QuizQuestionKind, QuizQuestionResult, QuizResult, QuizSubmission, Topic};
+
+/// Grades a learner's submission against a quiz.
+///
+/// Unanswered questions count as incorrect. Multiple-choice questions must have
+/// exactly the correct set of options selected.
+///
+/// # Arguments
+///
+/// * `quiz` - The quiz, including its answer keys
+/// * `submission` - The learner's answers
+///
+/// # Returns
+///
+/// The graded `QuizResult`.
+pub fn grade_quiz(quiz: &Quiz, submission: &QuizSubmission) -> QuizResult {
+    let questions: Vec<QuizQuestionResult> = quiz.questions.iter()
+        .map(|question| {
+            let answer = submission.answers.iter().find(|answer| answer.question_id == question.id);
+            let correct = match (&question.kind, answer) {
+                (QuizQuestionKind::MultipleChoice { correct_options, .. }, Some(answer)) => {
+                    let mut selected = answer.selected_options.clone();
+                    selected.sort();
+                    selected.dedup();
+                    let mut expected = correct_options.clone();
+                    expected.sort();
+                    !expected.is_empty() && selected == expected
+                }
+                (QuizQuestionKind::ShortAnswer { accepted_answers }, Some(answer)) => {
+                    let given = normalize_answer(answer.answer.as_deref().unwrap_or(""));
+                    !given.is_empty() && accepted_answers.iter().any(|accepted| normalize_answer(accepted) == given)
+                }
+                (_, None) => false,
+            };
+            QuizQuestionResult {
+                question_id: question.id.clone(),
+                correct,
+            }
+        })
+        .collect();
+
+    let correct_count = questions.iter().filter(|result| result.correct).count();
+    let score = if questions.is_empty() { 0.0 } else { correct_count as f32 / questions.len() as f32 };
+
+    QuizResult {
+        score,
+        passed: !questions.is_empty() && score >= quiz.pass_score,
+        questions,
+        graded_at: Utc::now(),
+    }
+}
+
+/// Normalizes a short answer for comparison.
+///
+/// Ignores case, surrounding and repeated whitespace, and trailing punctuation.
+fn normalize_answer(answer: &str) -> String {
+    answer.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_end_matches(['.', '!', '?', ';', ','])
+        .to_lowercase()
+}
+
+/// Removes the answer keys from every quiz in a topic.
+///
+/// Must be applied to every topic served to learners.
+pub fn strip_answer_keys(topic: &mut Topic) {
+    for quiz in topic.steps.iter_mut().filter_map(|step| step.quiz.as_mut()) {
+        for question in quiz.questions.iter_mut() {
+            match &mut question.kind {
+                QuizQuestionKind::MultipleChoice { correct_options, .. } => correct_options.clear(),
+                QuizQuestionKind::ShortAnswer { accepted_answers } => accepted_answers.clear(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{QuizAnswer, QuizQuestion};
+
+    fn sample_quiz() -> Quiz {
+        Quiz {
+            questions: vec![
+                QuizQuestion {
+                    id: "clone-command".to_string(),
+                    prompt: "Which command copies a remote repository to your machine?".to_string(),
+                    kind: QuizQuestionKind::MultipleChoice {
+                        options: vec!["git fork".to_string(), "git clone".to_string(), "git pull".to_string()],
+                        correct_options: vec![1],
+                    },
+                },
+                QuizQuestion {
+                    id: "default-branch".to_string(),
+                    prompt: "What is the default branch of a new GitHub repository called?".to_string(),
+                    kind: QuizQuestionKind::ShortAnswer {
+                        accepted_answers: vec!["main".to_string()],
+                    },
+                },
+            ],
+            pass_score: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_grade_quiz() {
+        let quiz = sample_quiz();
+
+        let passing = QuizSubmission {
+            answers: vec![
+                QuizAnswer { question_id: "clone-command".to_string(), selected_options: vec![1], answer: None },
+                QuizAnswer { question_id: "default-branch".to_string(), selected_options: vec![], answer: Some("  Main. ".to_string()) },
+            ],
+        };
+        let result = grade_quiz(&quiz, &passing);
+        assert_eq!(result.score, 1.0);
+        assert!(result.passed);
+
+        let failing = QuizSubmission {
+            answers: vec![
+                QuizAnswer { question_id: "clone-command".to_string(), selected_options: vec![1, 2], answer: None },
+            ],
+        };
+        let result = grade_quiz(&quiz, &failing);
+        assert_eq!(result.score, 0.0);
+        assert!(!result.passed);
+        assert!(result.questions.iter().all(|question| !question.correct));
+    }
+
+    #[test]
+    fn test_strip_answer_keys() {
+        let mut topic = crate::topics::get_github_setup_topic();
+        topic.steps[0].quiz = Some(sample_quiz());
+
+        strip_answer_keys(&mut topic);
+
+        let json = serde_json::to_string(&topic).unwrap();
+        assert!(json.contains("git clone"));
+        assert!(!json.contains("correct_options"));
+        assert!(!json.contains("accepted_answers"));
+    }
+}
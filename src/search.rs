@@ -0,0 +1,191 @@
+//! This module contains the keyword search across a learner's conversations.
+//!
+//! Matching ignores ASCII case. Every term of the query must appear in a message
+//! for it to match; results are ranked by how often the terms appear, favouring
+//! exact phrase matches and the tutor's explanations.
+
+use crate::types::{ConversationHistory, ConversationSearchResult, Topic};
+
+/// Default number of results returned by a search.
+pub const DEFAULT_SEARCH_LIMIT: usize = 20;
+/// Maximum number of results returned by a search.
+pub const MAX_SEARCH_LIMIT: usize = 50;
+
+/// Number of bytes of context shown before the first match in a snippet.
+const SNIPPET_CONTEXT: usize = 60;
+/// Approximate length of a snippet, in bytes.
+const SNIPPET_LENGTH: usize = 200;
+
+/// Searches the messages of a learner's conversations.
+///
+/// # Arguments
+///
+/// * `conversations` - The conversations to search, with the topic each belongs to
+///   and, for imported threads, the ID of the thread
+/// * `query` - The keywords to search for
+/// * `limit` - The maximum number of results to return
+///
+/// # Returns
+///
+/// The matching messages, best match first and newest first among equal matches.
+pub fn search_conversations(conversations: &[(Topic, Option<String>, ConversationHistory)], query: &str, limit: usize) -> Vec<ConversationSearchResult> {
+    let terms: Vec<String> = query.split_whitespace()
+        .map(|term| term.to_ascii_lowercase())
+        .collect();
+    if terms.is_empty() {
+        return vec![];
+    }
+    let phrase = terms.join(" ");
+
+    let mut results: Vec<ConversationSearchResult> = conversations.iter()
+        .flat_map(|(topic, thread_id, conversation)| {
+            conversation.messages.iter().enumerate().filter_map(|(position, message)| {
+                let content = message.content.to_ascii_lowercase();
+                let occurrences = terms.iter().try_fold(0, |total, term| match content.matches(term.as_str()).count() {
+                    0 => None,
+                    count => Some(total + count),
+                })?;
+
+                let phrase_bonus = if terms.len() > 1 && content.contains(&phrase) { 5 } else { 0 };
+                let tutor_bonus = if message.role == "assistant" { 1 } else { 0 };
+
+                Some(ConversationSearchResult {
+                    topic_id: topic.id.clone(),
+                    topic_title: topic.title.clone(),
+                    thread_id: thread_id.clone(),
+                    message_id: message.id.clone(),
+                    position,
+                    role: message.role.clone(),
+                    step_id: message.step_id.clone(),
+                    timestamp: message.timestamp,
+                    snippet: highlight_snippet(&message.content, &terms),
+                    score: occurrences + phrase_bonus + tutor_bonus,
+                })
+            })
+        })
+        .collect();
+
+    results.sort_by(|a, b| b.score.cmp(&a.score).then(b.timestamp.cmp(&a.timestamp)));
+    results.truncate(limit);
+    results
+}
+
+/// Builds an excerpt of a message around its first match, with every match in bold.
+fn highlight_snippet(content: &str, terms: &[String]) -> String {
+    let content = content.replace(['\n', '\r', '\t'], " ");
+    let lowercase = content.to_ascii_lowercase();
+
+    let first_match = terms.iter()
+        .filter_map(|term| lowercase.find(term.as_str()))
+        .min()
+        .unwrap_or(0);
+
+    // Start on a word boundary and stop on one, without splitting characters
+    let mut start = floor_char_boundary(&content, first_match.saturating_sub(SNIPPET_CONTEXT));
+    if start > 0 {
+        start = content[start..first_match].find(' ').map(|space| start + space + 1).unwrap_or(start);
+    }
+    let mut end = floor_char_boundary(&content, (start + SNIPPET_LENGTH).max(first_match));
+    if end < content.len() {
+        end = content[..end].rfind(' ').filter(|&space| space > first_match).unwrap_or(end);
+    }
+
+    let mut ranges: Vec<(usize, usize)> = terms.iter()
+        .flat_map(|term| lowercase[start..end].match_indices(term.as_str()).map(|(index, _)| (start + index, start + index + term.len())).collect::<Vec<_>>())
+        .collect();
+    ranges.sort();
+
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push('…');
+    }
+    let mut cursor = start;
+    for (range_start, range_end) in ranges {
+        if range_start < cursor {
+            // Overlapping match: extend the highlight that is already open
+            if range_end > cursor {
+                snippet.insert_str(snippet.len() - 2, &content[cursor..range_end]);
+                cursor = range_end;
+            }
+            continue;
+        }
+        snippet.push_str(&content[cursor..range_start]);
+        snippet.push_str("**");
+        snippet.push_str(&content[range_start..range_end]);
+        snippet.push_str("**");
+        cursor = range_end;
+    }
+    snippet.push_str(&content[cursor..end]);
+    if end < content.len() {
+        snippet.push('…');
+    }
+
+    snippet.trim().to_string()
+}
+
+/// Returns the largest character boundary of `text` that is not after `index`.
+fn floor_char_boundary(text: &str, index: usize) -> usize {
+    let mut index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conversation::push_message;
+    use crate::topics::get_github_setup_topic;
+
+    fn sample_conversations() -> Vec<(Topic, Option<String>, ConversationHistory)> {
+        let topic = get_github_setup_topic();
+        let mut conversation = ConversationHistory {
+            topic_id: topic.id.clone(),
+            messages: vec![],
+            next_message_id: 0,
+        };
+        push_message(&mut conversation, "user", "How do SSH keys work?".to_string(), Some("ssh-keys".to_string()));
+        push_message(&mut conversation, "assistant", "SSH keys come in pairs. Your public SSH key is shared with GitHub, while the private key stays on your machine.".to_string(), Some("ssh-keys".to_string()));
+        push_message(&mut conversation, "user", "Thanks! What about HTTPS?".to_string(), Some("ssh-keys".to_string()));
+        let mut thread = ConversationHistory {
+            topic_id: topic.id.clone(),
+            messages: vec![],
+            next_message_id: 0,
+        };
+        push_message(&mut thread, "user", "Should I use HTTPS or SSH?".to_string(), None);
+        thread.messages[0].timestamp = conversation.messages[2].timestamp + chrono::Duration::seconds(1);
+        vec![(topic.clone(), None, conversation), (topic, Some("t1".to_string()), thread)]
+    }
+
+    #[test]
+    fn test_search_conversations_ranks_results() {
+        let conversations = sample_conversations();
+
+        let results = search_conversations(&conversations, "ssh key", 10);
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].message_id, "m2");
+        assert_eq!(results[0].position, 1);
+        assert_eq!(results[1].message_id, "m1");
+        assert!(results.iter().all(|result| result.thread_id.is_none()));
+        assert!(search_conversations(&conversations, "ssh docker", 10).is_empty());
+
+        let results = search_conversations(&conversations, "https", 10);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].thread_id.as_deref(), Some("t1"));
+        assert_eq!(results[1].thread_id, None);
+    }
+
+    #[test]
+    fn test_highlight_snippet() {
+        let terms = vec!["ssh".to_string(), "ssh key".to_string()];
+        assert_eq!(highlight_snippet("Add your SSH key to GitHub", &terms), "Add your **SSH key** to GitHub");
+
+        let long = format!("{} the SSH agent {}", "word ".repeat(40), "word ".repeat(60));
+        let snippet = highlight_snippet(&long, &["ssh".to_string()]);
+        assert!(snippet.starts_with('…'));
+        assert!(snippet.ends_with('…'));
+        assert!(snippet.contains("the **SSH** agent"));
+    }
+}
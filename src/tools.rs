@@ -0,0 +1,306 @@
+//! This module defines the server-side tools the tutor can call while answering.
+//!
+//! Each tool has a definition sent to the model and an implementation run by a
+//! `ToolSession` against the learner's topic, progress and the documentation
+//! corpus. Topics can restrict the tutor to some of the tools with an allow-list.
+//! The loop sending tool results back to the model lives in `claude`.
+
+use serde_json::{json, Value};
+use crate::framing;
+use crate::rag;
+use crate::types::{ClaudeTool, DocIndex, Progress, QuizQuestionKind, Step, Topic};
+use crate::versioning;
+
+/// Looks up the details of a step of the topic.
+pub const GET_STEP_DETAILS: &str = "get_step_details";
+/// Marks the learner's current step as completed.
+pub const MARK_STEP_COMPLETE: &str = "mark_step_complete";
+/// Produces practice questions for a step.
+pub const GENERATE_QUIZ: &str = "generate_quiz";
+/// Searches the documentation corpus.
+pub const SEARCH_DOCS: &str = "search_docs";
+
+/// Names of all the tools, in the order they are offered to the model.
+pub const TOOL_NAMES: &[&str] = &[GET_STEP_DETAILS, MARK_STEP_COMPLETE, GENERATE_QUIZ, SEARCH_DOCS];
+
+/// Number of practice questions produced when the model does not ask for a number.
+const DEFAULT_QUIZ_QUESTIONS: usize = 3;
+/// Maximum number of practice questions produced at once.
+const MAX_QUIZ_QUESTIONS: usize = 5;
+
+/// Represents the result of a tool call, sent back to the model.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolOutput {
+    /// The result, or a description of why the call failed
+    pub content: String,
+    /// Whether the call failed
+    pub is_error: bool,
+}
+
+impl ToolOutput {
+    fn success(value: Value) -> Self {
+        ToolOutput { content: value.to_string(), is_error: false }
+    }
+
+    fn error(message: impl Into<String>) -> Self {
+        ToolOutput { content: message.into(), is_error: true }
+    }
+}
+
+/// Runs the tools called by the model.
+pub trait ToolExecutor {
+    /// Runs a tool with the arguments given by the model.
+    fn execute(&mut self, name: &str, input: &Value) -> ToolOutput;
+}
+
+/// Returns the definition of a tool, or `None` if there is no tool with that name.
+pub fn tool_definition(name: &str) -> Option<ClaudeTool> {
+    let (description, input_schema) = match name {
+        GET_STEP_DETAILS => (
+            "Looks up a step of the current topic: its title, position, what it covers, its suggested questions, whether it ends with a quiz or an exercise, and whether the learner completed it. Defaults to the learner's current step.",
+            json!({
+                "type": "object",
+                "properties": {
+                    "step_id": {"type": "string", "description": "The ID of the step; the current step if omitted"}
+                }
+            }),
+        ),
+        MARK_STEP_COMPLETE => (
+            "Marks the learner's current step as completed and moves them to the next step. Only call this once the learner has confirmed they successfully did what the step asks, for example that a command worked. Steps ending with a quiz or an exercise cannot be marked complete; the learner completes them by passing the quiz or the exercise.",
+            json!({
+                "type": "object",
+                "properties": {
+                    "step_id": {"type": "string", "description": "The ID of the learner's current step"}
+                },
+                "required": ["step_id"]
+            }),
+        ),
+        GENERATE_QUIZ => (
+            "Produces practice questions about a step for the learner to answer in the chat. They do not affect the learner's progress. Ask them one at a time and give feedback on each answer. Defaults to the learner's current step.",
+            json!({
+                "type": "object",
+                "properties": {
+                    "step_id": {"type": "string", "description": "The ID of the step; the current step if omitted"},
+                    "count": {"type": "integer", "minimum": 1, "maximum": MAX_QUIZ_QUESTIONS, "description": "The number of questions"}
+                }
+            }),
+        ),
+        SEARCH_DOCS => (
+            "Searches the curated Git, Docker and Kubernetes documentation for excerpts relevant to a query. Use it when the excerpts already provided do not cover the learner's question.",
+            json!({
+                "type": "object",
+                "properties": {
+                    "query": {"type": "string", "description": "What to search for"}
+                },
+                "required": ["query"]
+            }),
+        ),
+        _ => return None,
+    };
+
+    Some(ClaudeTool {
+        name: name.to_string(),
+        description: description.to_string(),
+        input_schema,
+    })
+}
+
+/// Returns the definitions of the tools the tutor may call in a topic.
+///
+/// Every tool is allowed when the topic has no allow-list. Unknown names are ignored.
+pub fn allowed_tools(topic: &Topic) -> Vec<ClaudeTool> {
+    TOOL_NAMES.iter()
+        .filter(|name| match &topic.tools {
+            Some(allowed) => allowed.iter().any(|allowed| allowed == *name),
+            None => true,
+        })
+        .filter_map(|name| tool_definition(name))
+        .collect()
+}
+
+/// Runs tools for a learner on a topic.
+///
+/// Changes to the learner's progress are made in memory; the caller stores the
+/// progress when `progress_changed` is set.
+pub struct ToolSession<'a> {
+    /// The topic version the learner's progress is pinned to
+    topic: &'a Topic,
+    /// The index of the documentation corpus
+    index: &'a DocIndex,
+    /// The learner's progress
+    pub progress: Progress,
+    /// Whether a tool changed the learner's progress
+    pub progress_changed: bool,
+}
+
+impl<'a> ToolSession<'a> {
+    /// Creates a session for a learner's progress on the topic version it is pinned to.
+    pub fn new(topic: &'a Topic, index: &'a DocIndex, progress: Progress) -> Self {
+        ToolSession {
+            topic,
+            index,
+            progress,
+            progress_changed: false,
+        }
+    }
+
+    /// Returns the step named by the `step_id` argument, or the current step if there is none.
+    fn find_step(&self, input: &Value) -> Result<(usize, &'a Step), ToolOutput> {
+        let topic = self.topic;
+        match input.get("step_id").and_then(Value::as_str) {
+            Some(step_id) => topic.steps.iter().enumerate()
+                .find(|(_, step)| step.id == step_id)
+                .ok_or_else(|| ToolOutput::error(format!("Step '{}' not found", step_id))),
+            None => topic.steps.get(self.progress.current_step)
+                .map(|step| (self.progress.current_step, step))
+                .ok_or_else(|| ToolOutput::error("The learner has finished every step")),
+        }
+    }
+
+    fn get_step_details(&self, input: &Value) -> ToolOutput {
+        let (index, step) = match self.find_step(input) {
+            Ok(found) => found,
+            Err(output) => return output,
+        };
+        let kind = if step.quiz.is_some() { "quiz" } else if step.exercise.is_some() { "exercise" } else { "lesson" };
+
+        ToolOutput::success(json!({
+            "step_id": step.id,
+            "title": step.title,
+            "position": format!("{} of {}", index + 1, self.topic.steps.len()),
+            "kind": kind,
+            "covers": step.prompt,
+            "suggested_questions": step.suggested_questions,
+            "exercise_instructions": step.exercise.as_ref().map(|exercise| exercise.instructions.clone()),
+            "completed": self.progress.completed_steps.contains(&index),
+            "is_current_step": index == self.progress.current_step,
+        }))
+    }
+
+    fn mark_step_complete(&mut self, input: &Value) -> ToolOutput {
+        let step_id = match input.get("step_id").and_then(Value::as_str) {
+            Some(step_id) => step_id,
+            None => return ToolOutput::error("step_id is required"),
+        };
+        let current = self.progress.current_step;
+        let step = match self.topic.steps.get(current) {
+            Some(step) if step.id == step_id => step,
+            Some(step) => return ToolOutput::error(format!("Only the learner's current step, '{}', can be marked complete", step.id)),
+            None => return ToolOutput::error("The learner has finished every step"),
+        };
+        if step.quiz.is_some() {
+            return ToolOutput::error("Quiz steps are completed by submitting a passing quiz");
+        }
+        if step.exercise.is_some() {
+            return ToolOutput::error("Exercise steps are completed by submitting a passing answer");
+        }
+
+        if versioning::mark_step_completed(&mut self.progress, self.topic, current) {
+            self.progress_changed = true;
+        }
+        ToolOutput::success(json!({
+            "completed_step_id": step.id,
+            "next_step": self.topic.steps.get(self.progress.current_step).map(|next| json!({"step_id": next.id, "title": next.title})),
+        }))
+    }
+
+    fn generate_quiz(&self, input: &Value) -> ToolOutput {
+        let (_, step) = match self.find_step(input) {
+            Ok(found) => found,
+            Err(output) => return output,
+        };
+        let count = input.get("count").and_then(Value::as_u64)
+            .map(|count| (count as usize).clamp(1, MAX_QUIZ_QUESTIONS))
+            .unwrap_or(DEFAULT_QUIZ_QUESTIONS);
+
+        // Questions of the step's own quiz come first, without their answer keys
+        let quiz_questions = step.quiz.iter()
+            .flat_map(|quiz| quiz.questions.iter())
+            .map(|question| match &question.kind {
+                QuizQuestionKind::MultipleChoice { options, .. } => json!({"question": question.prompt, "options": options}),
+                QuizQuestionKind::ShortAnswer { .. } => json!({"question": question.prompt}),
+            });
+        let open_questions = step.suggested_questions.iter().map(|question| json!({"question": question}));
+        let questions: Vec<Value> = quiz_questions.chain(open_questions).take(count).collect();
+
+        ToolOutput::success(json!({
+            "step_id": step.id,
+            "covers": step.prompt,
+            "questions": questions,
+        }))
+    }
+
+    fn search_docs(&self, input: &Value) -> ToolOutput {
+        let query = match input.get("query").and_then(Value::as_str) {
+            Some(query) if !query.trim().is_empty() => query,
+            _ => return ToolOutput::error("query is required"),
+        };
+
+        let results: Vec<Value> = rag::retrieve(self.index, query, rag::RETRIEVAL_LIMIT).iter()
+            .map(|chunk| json!({
+                "title": format!("{} - {}", chunk.title, chunk.heading),
+                "source_url": chunk.source_url,
+                "content": framing::neutralize_tags(&chunk.content),
+            }))
+            .collect();
+        ToolOutput::success(json!({ "results": results }))
+    }
+}
+
+impl ToolExecutor for ToolSession<'_> {
+    fn execute(&mut self, name: &str, input: &Value) -> ToolOutput {
+        match name {
+            GET_STEP_DETAILS => self.get_step_details(input),
+            MARK_STEP_COMPLETE => self.mark_step_complete(input),
+            GENERATE_QUIZ => self.generate_quiz(input),
+            SEARCH_DOCS => self.search_docs(input),
+            _ => ToolOutput::error(format!("Unknown tool '{}'", name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::topics::get_github_setup_topic;
+
+    #[test]
+    fn test_allowed_tools() {
+        let mut topic = get_github_setup_topic();
+        let names = |topic: &Topic| allowed_tools(topic).into_iter().map(|tool| tool.name).collect::<Vec<_>>();
+
+        assert_eq!(names(&topic), TOOL_NAMES);
+        topic.tools = Some(vec![SEARCH_DOCS.to_string(), GET_STEP_DETAILS.to_string(), "rm_rf".to_string()]);
+        assert_eq!(names(&topic), vec![GET_STEP_DETAILS, SEARCH_DOCS]);
+        topic.tools = Some(vec![]);
+        assert!(allowed_tools(&topic).is_empty());
+    }
+
+    #[test]
+    fn test_tool_session() {
+        let topic = get_github_setup_topic();
+        let index = rag::build_index(&rag::builtin_documents());
+        let mut session = ToolSession::new(&topic, &index, Progress::new(&topic.id, topic.version));
+
+        let details: Value = serde_json::from_str(&session.execute(GET_STEP_DETAILS, &json!({})).content).unwrap();
+        assert_eq!(details["step_id"], topic.steps[0].id.as_str());
+        assert_eq!(details["position"], format!("1 of {}", topic.steps.len()));
+
+        let refused = session.execute(MARK_STEP_COMPLETE, &json!({"step_id": topic.steps[1].id}));
+        assert!(refused.is_error);
+        assert!(!session.progress_changed);
+
+        let completed: Value = serde_json::from_str(&session.execute(MARK_STEP_COMPLETE, &json!({"step_id": topic.steps[0].id})).content).unwrap();
+        assert_eq!(completed["next_step"]["step_id"], topic.steps[1].id.as_str());
+        assert!(session.progress_changed);
+        assert_eq!(session.progress.current_step, 1);
+        assert_eq!(session.progress.completed_step_ids, vec![topic.steps[0].id.clone()]);
+
+        let quiz: Value = serde_json::from_str(&session.execute(GENERATE_QUIZ, &json!({"count": 2})).content).unwrap();
+        assert_eq!(quiz["questions"].as_array().unwrap().len(), 2);
+
+        let search: Value = serde_json::from_str(&session.execute(SEARCH_DOCS, &json!({"query": "git commit"})).content).unwrap();
+        assert!(!search["results"].as_array().unwrap().is_empty());
+        assert!(session.execute("delete_repository", &json!({})).is_error);
+    }
+}
@@ -1,9 +1,224 @@
-use crate::types::{Topic, Step};
+use std::collections::HashMap;
+use worker::*;
+use crate::types::{Topic, Step, Difficulty, TopicQuery};
+use crate::validation;
+use chrono::{TimeZone, Utc};
+
+/// Key of the KV entry listing the ids of all authored topics.
+const TOPIC_INDEX_KEY: &str = "topic_index";
 
 pub fn get_all_topics() -> Vec<Topic> {
     vec![get_github_setup_topic()]
 }
 
+/// Returns the built-in topic with the given id, if any.
+pub fn get_builtin_topic(topic_id: &str) -> Option<Topic> {
+    get_all_topics().into_iter().find(|topic| topic.id == topic_id)
+}
+
+fn topic_key(topic_id: &str) -> String {
+    format!("topic_{}", topic_id)
+}
+
+/// Loads a topic from the data store, falling back to the built-in topics.
+///
+/// Authored topics shadow built-in topics with the same id. The stored topic is
+/// returned as is, even if it no longer passes validation, so that instructors
+/// can repair it; learners are served topics by `load_published_topic`.
+pub async fn load_topic(kv: &kv::KvStore, topic_id: &str) -> Result<Option<Topic>> {
+    match kv.get(&topic_key(topic_id)).json::<Topic>().await? {
+        Some(topic) => Ok(Some(topic)),
+        None => Ok(get_builtin_topic(topic_id)),
+    }
+}
+
+/// Loads a topic only if it is published, as served to learners.
+///
+/// Stored topics that fail validation are rejected and the built-in topic, if
+/// any, is used instead.
+pub async fn load_published_topic(kv: &kv::KvStore, topic_id: &str) -> Result<Option<Topic>> {
+    let topic = match kv.get(&topic_key(topic_id)).json::<Topic>().await? {
+        Some(topic) if !topic.published => return Ok(None),
+        Some(topic) => {
+            // The catalogue is only needed to check prerequisites
+            let known_topic_ids = if topic.prerequisites.is_empty() { vec![] } else { load_known_topic_ids(kv).await? };
+            match validation::validate_topic(&topic, &known_topic_ids) {
+                Ok(()) => Some(topic),
+                Err(report) => {
+                    console_error!("Rejecting invalid topic: {}", report);
+                    get_builtin_topic(topic_id)
+                }
+            }
+        }
+        None => get_builtin_topic(topic_id),
+    };
+    Ok(topic.filter(|topic| topic.published))
+}
+
+/// Loads the built-in and authored topics, optionally including unpublished ones.
+///
+/// Listings for learners leave out stored topics that fail validation; listings
+/// for instructors, which include unpublished topics, keep them so they can be repaired.
+pub async fn load_all_topics(kv: &kv::KvStore, include_unpublished: bool) -> Result<Vec<Topic>> {
+    let mut topics = get_all_topics();
+    let topic_index = load_topic_index(kv).await?;
+    let mut known_topic_ids: Vec<String> = topics.iter().map(|topic| topic.id.clone()).collect();
+    for topic_id in &topic_index {
+        if !known_topic_ids.contains(topic_id) {
+            known_topic_ids.push(topic_id.clone());
+        }
+    }
+
+    for topic_id in topic_index {
+        if let Some(topic) = kv.get(&topic_key(&topic_id)).json::<Topic>().await? {
+            if !include_unpublished {
+                if let Err(report) = validation::validate_topic(&topic, &known_topic_ids) {
+                    console_error!("Rejecting invalid topic: {}", report);
+                    continue;
+                }
+            }
+            match topics.iter_mut().find(|t| t.id == topic.id) {
+                Some(existing) => *existing = topic,
+                None => topics.push(topic),
+            }
+        }
+    }
+
+    if !include_unpublished {
+        topics.retain(|topic| topic.published);
+    }
+    Ok(topics)
+}
+
+/// Stores an authored topic and records it in the topic index.
+pub async fn save_topic(kv: &kv::KvStore, topic: &Topic) -> Result<()> {
+    kv.put(&topic_key(&topic.id), serde_json::to_string(topic)?)?
+        .execute().await?;
+
+    let mut index = load_topic_index(kv).await?;
+    if !index.contains(&topic.id) {
+        index.push(topic.id.clone());
+        kv.put(TOPIC_INDEX_KEY, serde_json::to_string(&index)?)?
+            .execute().await?;
+    }
+    Ok(())
+}
+
+/// Stores a new version of a topic, archiving the version it replaces.
+///
+/// The archived version remains available to learners whose progress is pinned to it.
+///
+/// # Arguments
+///
+/// * `kv` - The data store
+/// * `previous` - The currently stored version of the topic
+/// * `topic` - The new content, which is assigned the next version number
+pub async fn save_topic_revision(kv: &kv::KvStore, previous: &Topic, topic: &mut Topic) -> Result<()> {
+    kv.put(&topic_version_key(&previous.id, previous.version), serde_json::to_string(previous)?)?
+        .execute().await?;
+
+    topic.version = previous.version + 1;
+    topic.last_updated = Utc::now();
+    save_topic(kv, topic).await
+}
+
+/// Loads a specific version of a topic.
+///
+/// # Returns
+///
+/// The given current version if it is the one requested, the archived version
+/// otherwise, or `None` if the requested version was never archived.
+pub async fn load_topic_version(kv: &kv::KvStore, current: &Topic, version: u32) -> Result<Option<Topic>> {
+    if version == current.version {
+        return Ok(Some(current.clone()));
+    }
+
+    Ok(kv.get(&topic_version_key(&current.id, version)).json::<Topic>().await?)
+}
+
+fn topic_version_key(topic_id: &str, version: u32) -> String {
+    format!("topic_{}_v{}", topic_id, version)
+}
+
+/// Removes an authored topic from the data store.
+///
+/// Returns `false` if no authored topic with the given id exists.
+pub async fn delete_topic(kv: &kv::KvStore, topic_id: &str) -> Result<bool> {
+    let mut index = load_topic_index(kv).await?;
+    if !index.iter().any(|id| id == topic_id) {
+        return Ok(false);
+    }
+
+    kv.delete(&topic_key(topic_id)).await?;
+    index.retain(|id| id != topic_id);
+    kv.put(TOPIC_INDEX_KEY, serde_json::to_string(&index)?)?
+        .execute().await?;
+    Ok(true)
+}
+
+async fn load_topic_index(kv: &kv::KvStore) -> Result<Vec<String>> {
+    Ok(kv.get(TOPIC_INDEX_KEY).json::<Vec<String>>().await?.unwrap_or_default())
+}
+
+/// Returns the IDs of all built-in and authored topics.
+pub async fn load_known_topic_ids(kv: &kv::KvStore) -> Result<Vec<String>> {
+    let mut topic_ids: Vec<String> = get_all_topics().into_iter().map(|topic| topic.id).collect();
+    for topic_id in load_topic_index(kv).await? {
+        if !topic_ids.contains(&topic_id) {
+            topic_ids.push(topic_id);
+        }
+    }
+    Ok(topic_ids)
+}
+
+/// Returns the topics matching the given query, best matches first when searching.
+pub fn search_topics(topics: Vec<Topic>, query: &TopicQuery) -> Vec<Topic> {
+    let terms: Vec<String> = query.q.as_deref()
+        .unwrap_or("")
+        .split_whitespace()
+        .map(|term| term.to_lowercase())
+        .collect();
+
+    let mut matches: Vec<(usize, Topic)> = topics.into_iter()
+        .filter(|topic| match &query.tag {
+            Some(tag) => topic.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)),
+            None => true,
+        })
+        .filter(|topic| match query.difficulty {
+            Some(difficulty) => topic.difficulty == difficulty,
+            None => true,
+        })
+        .filter_map(|topic| search_score(&topic, &terms).map(|score| (score, topic)))
+        .collect();
+
+    // Stable sort keeps the catalogue order for equally ranked topics
+    matches.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    matches.into_iter().map(|(_, topic)| topic).collect()
+}
+
+/// Scores a topic against the search terms, or returns `None` if any term is missing.
+///
+/// Matches in the title weigh more than matches in the description, which in turn
+/// weigh more than matches in step titles.
+fn search_score(topic: &Topic, terms: &[String]) -> Option<usize> {
+    let title = topic.title.to_lowercase();
+    let description = topic.description.to_lowercase();
+    let step_titles: Vec<String> = topic.steps.iter().map(|step| step.title.to_lowercase()).collect();
+
+    terms.iter().try_fold(0, |score, term| {
+        let term_score = if title.contains(term.as_str()) {
+            3
+        } else if description.contains(term.as_str()) {
+            2
+        } else if step_titles.iter().any(|step_title| step_title.contains(term.as_str())) {
+            1
+        } else {
+            return None;
+        };
+        Some(score + term_score)
+    })
+}
+
 pub fn get_github_setup_topic() -> Topic {
     Topic {
         id: "github-setup".to_string(),
@@ -20,8 +235,19 @@ Here's a quick overview of how to use this tutorial:
 3. **Ask Questions**: Feel free to type any questions or ask for clarification at any time.
 
 **Let's begin!** Click the 'Next Step' button to start your GitHub setup journey."#.to_string(),
+        difficulty: Difficulty::Beginner,
+        estimated_duration_minutes: 45,
+        tags: vec!["git".to_string(), "github".to_string(), "version-control".to_string()],
+        author: "DevOps AI Team".to_string(),
+        version: 1,
+        last_updated: Utc.with_ymd_and_hms(2024, 8, 1, 0, 0, 0).unwrap(),
+        published: true,
+        prerequisites: vec![],
+        translations: HashMap::new(),
+        tools: None,
         steps: vec![
             Step {
+                id: "introduction-to-github".to_string(),
                 title: "Introduction to GitHub".to_string(),
                 prompt: "Provide a brief introduction to GitHub, explaining what it is and its main purposes for developers. Keep the explanation simple and engaging for beginners.".to_string(),
                 suggested_questions: vec![
@@ -29,8 +255,11 @@ Here's a quick overview of how to use this tutorial:
                     "Why do developers use GitHub?".to_string(),
                     "Is GitHub only for programmers?".to_string(),
                 ],
+                quiz: None,
+                exercise: None,
             },
             Step {
+                id: "create-account".to_string(),
                 title: "Create a GitHub account".to_string(),
                 prompt: "Outline a concise, step-by-step guide on how to create a GitHub account. Focus only on the essential steps, keeping the instructions clear and easy to follow for new users.".to_string(),
                 suggested_questions: vec![
@@ -38,8 +267,11 @@ Here's a quick overview of how to use this tutorial:
                     "Is it free to create a GitHub account?".to_string(),
                     "Can I use my work email to sign up?".to_string(),
                 ],
+                quiz: None,
+                exercise: None,
             },
             Step {
+                id: "install-git".to_string(),
                 title: "Install Git on your local machine".to_string(),
                 prompt: "Explain how to install Git on a local machine. Provide clear instructions for common operating systems (Windows, macOS, Linux). Keep the explanation concise but informative.".to_string(),
                 suggested_questions: vec![
@@ -47,8 +279,11 @@ Here's a quick overview of how to use this tutorial:
                     "Are there different installation methods for Windows and Mac?".to_string(),
                     "Do I need admin rights to install Git?".to_string(),
                 ],
+                quiz: None,
+                exercise: None,
             },
             Step {
+                id: "ssh-keys".to_string(),
                 title: "Set up SSH keys for secure authentication".to_string(),
                 prompt: "Provide a brief, step-by-step guide on how to set up SSH keys for GitHub authentication. Ensure the instructions are clear and easy to follow for users who might be new to this concept.".to_string(),
                 suggested_questions: vec![
@@ -56,8 +291,11 @@ Here's a quick overview of how to use this tutorial:
                     "Can I use the same SSH key for multiple GitHub accounts?".to_string(),
                     "What if I lose my SSH key?".to_string(),
                 ],
+                quiz: None,
+                exercise: None,
             },
             Step {
+                id: "configure-git".to_string(),
                 title: "Configure Git with your GitHub credentials".to_string(),
                 prompt: "Explain how to configure Git with GitHub credentials. Focus on the essential commands, providing clear instructions for users to follow. Include any necessary explanations of what each command does.".to_string(),
                 suggested_questions: vec![
@@ -65,8 +303,11 @@ Here's a quick overview of how to use this tutorial:
                     "Can I use different Git configurations for different projects?".to_string(),
                     "What's the difference between local and global Git configurations?".to_string(),
                 ],
+                quiz: None,
+                exercise: None,
             },
             Step {
+                id: "create-repository".to_string(),
                 title: "Create your first repository".to_string(),
                 prompt: "Describe how to create a new repository on GitHub. Cover only the basic steps, ensuring the instructions are clear and concise for new users.".to_string(),
                 suggested_questions: vec![
@@ -74,8 +315,11 @@ Here's a quick overview of how to use this tutorial:
                     "Should I initialize the repository with a README?".to_string(),
                     "How do I choose a good name for my repository?".to_string(),
                 ],
+                quiz: None,
+                exercise: None,
             },
             Step {
+                id: "clone-repository".to_string(),
                 title: "Clone the repository to your local machine".to_string(),
                 prompt: "Explain how to clone a GitHub repository to a local machine. Include the basic command and a brief explanation of what cloning means and why it's important.".to_string(),
                 suggested_questions: vec![
@@ -83,8 +327,11 @@ Here's a quick overview of how to use this tutorial:
                     "What's the difference between cloning with HTTPS and SSH?".to_string(),
                     "Where should I clone my repository to on my local machine?".to_string(),
                 ],
+                quiz: None,
+                exercise: None,
             },
             Step {
+                id: "commit-changes".to_string(),
                 title: "Make changes and commit them".to_string(),
                 prompt: "Provide instructions on how to make changes to files and commit them using Git. Focus on the essential commands, explaining each step clearly for new users.".to_string(),
                 suggested_questions: vec![
@@ -92,8 +339,11 @@ Here's a quick overview of how to use this tutorial:
                     "How often should I commit my changes?".to_string(),
                     "Can I undo a commit?".to_string(),
                 ],
+                quiz: None,
+                exercise: None,
             },
             Step {
+                id: "push-changes".to_string(),
                 title: "Push changes to GitHub".to_string(),
                 prompt: "Explain how to push local commits to GitHub. Include the basic command and a brief explanation of what pushing means in the context of Git and GitHub.".to_string(),
                 suggested_questions: vec![
@@ -101,8 +351,11 @@ Here's a quick overview of how to use this tutorial:
                     "Can I push to someone else's repository?".to_string(),
                     "How do I know if my push was successful?".to_string(),
                 ],
+                quiz: None,
+                exercise: None,
             },
             Step {
+                id: "branch-and-pull-request".to_string(),
                 title: "Create a branch and make a pull request".to_string(),
                 prompt: "Describe how to create a branch and make a pull request on GitHub. Cover the essential steps, explaining the concepts of branching and pull requests in a way that's easy for beginners to understand.".to_string(),
                 suggested_questions: vec![
@@ -110,8 +363,11 @@ Here's a quick overview of how to use this tutorial:
                     "How do I name my branches?".to_string(),
                     "What happens after I create a pull request?".to_string(),
                 ],
+                quiz: None,
+                exercise: None,
             },
             Step {
+                id: "collaborate".to_string(),
                 title: "Collaborate on a project".to_string(),
                 prompt: "Provide an overview of how to start collaborating on a GitHub project. Mention key concepts like forking and contributing, explaining them in a way that's accessible to new users. Include basic steps for getting involved in open-source projects.".to_string(),
                 suggested_questions: vec![
@@ -119,7 +375,31 @@ Here's a quick overview of how to use this tutorial:
                     "What's the difference between forking and cloning?".to_string(),
                     "How do I suggest changes to someone else's project?".to_string(),
                 ],
+                quiz: None,
+                exercise: None,
             },
         ],
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_topics() {
+        let topics = get_all_topics();
+
+        let by_step_title = TopicQuery { q: Some("SSH keys".to_string()), ..Default::default() };
+        assert_eq!(search_topics(topics.clone(), &by_step_title).len(), 1);
+
+        let by_tag = TopicQuery { tag: Some("GitHub".to_string()), ..Default::default() };
+        assert_eq!(search_topics(topics.clone(), &by_tag).len(), 1);
+
+        let wrong_difficulty = TopicQuery { difficulty: Some(Difficulty::Advanced), ..Default::default() };
+        assert!(search_topics(topics.clone(), &wrong_difficulty).is_empty());
+
+        let missing_term = TopicQuery { q: Some("github kubernetes".to_string()), ..Default::default() };
+        assert!(search_topics(topics, &missing_term).is_empty());
+    }
+}
@@ -1,10 +1,11 @@
 //! This module contains all the data structures used in the DevOps AI API.
 
+use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
 
 /// Represents a learning topic in the DevOps AI system.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Topic {
     /// Unique identifier for the topic
     pub id: String,
@@ -16,17 +17,271 @@ pub struct Topic {
     pub steps: Vec<Step>,
     /// Initial message to be displayed when the topic is started
     pub initial_message: String,
+    /// Difficulty level of the topic
+    pub difficulty: Difficulty,
+    /// Estimated time to complete the topic, in minutes
+    pub estimated_duration_minutes: u32,
+    /// Tags used to categorise the topic
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Author of the topic content
+    pub author: String,
+    /// Version of the topic content, assigned by the server
+    #[serde(default)]
+    pub version: u32,
+    /// When the topic content was last updated, assigned by the server
+    #[serde(default)]
+    pub last_updated: DateTime<Utc>,
+    /// Whether the topic is visible to learners
+    #[serde(default)]
+    pub published: bool,
+    /// IDs of the topics learners should complete before this one
+    #[serde(default)]
+    pub prerequisites: Vec<String>,
+    /// Translations of the topic text, keyed by locale
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub translations: HashMap<String, TopicTranslation>,
+    /// Names of the tools the tutor may call in this topic; every tool when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<String>>,
+}
+
+/// Represents the translated text of a topic in a single locale.
+///
+/// Fields that are not set fall back to the English text of the topic.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TopicTranslation {
+    /// Translated title of the topic
+    pub title: Option<String>,
+    /// Translated description of the topic
+    pub description: Option<String>,
+    /// Translated initial message of the topic
+    pub initial_message: Option<String>,
+    /// Translations of the steps, keyed by step ID
+    #[serde(default)]
+    pub steps: HashMap<String, StepTranslation>,
+}
+
+/// Represents the translated text of a step in a single locale.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct StepTranslation {
+    /// Translated title of the step
+    pub title: Option<String>,
+    /// Translated prompt of the step
+    pub prompt: Option<String>,
+    /// Translated suggested questions of the step
+    pub suggested_questions: Option<Vec<String>>,
+}
+
+/// Represents the difficulty level of a topic.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Beginner,
+    Intermediate,
+    Advanced,
+}
+
+impl std::str::FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "beginner" => Ok(Difficulty::Beginner),
+            "intermediate" => Ok(Difficulty::Intermediate),
+            "advanced" => Ok(Difficulty::Advanced),
+            _ => Err(format!("Unknown difficulty: {}", s)),
+        }
+    }
+}
+
+/// Represents the filters accepted by the topic listing endpoint.
+#[derive(Debug, Default)]
+pub struct TopicQuery {
+    /// Only include topics carrying this tag
+    pub tag: Option<String>,
+    /// Only include topics of this difficulty
+    pub difficulty: Option<Difficulty>,
+    /// Free-text search over titles, descriptions and step titles
+    pub q: Option<String>,
 }
 
 /// Represents a single step within a learning topic.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Step {
+    /// Stable identifier of the step, preserved across topic versions
+    #[serde(default)]
+    pub id: String,
     /// Title of the step
     pub title: String,
     /// Prompt to be sent to the AI for this step
     pub prompt: String,
     /// Suggested questions for this step
     pub suggested_questions: Vec<String>,
+    /// Quiz the learner must pass to complete this step, if it is a quiz step
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quiz: Option<Quiz>,
+    /// Open-ended exercise graded by the AI, if it is an exercise step
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exercise: Option<Exercise>,
+}
+
+/// Represents an open-ended exercise graded by the AI against a rubric.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Exercise {
+    /// The task given to the learner
+    pub instructions: String,
+    /// The criteria the answer is graded against
+    pub rubric: Vec<RubricCriterion>,
+    /// Fraction of rubric points required to pass (0.0 to 1.0)
+    #[serde(default = "default_pass_score")]
+    pub pass_score: f32,
+}
+
+/// Represents a single criterion of an exercise rubric.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RubricCriterion {
+    /// Identifier of the criterion, unique within the rubric
+    pub id: String,
+    /// What a good answer must contain or do
+    pub description: String,
+    /// Points awarded when the criterion is met
+    #[serde(default = "default_criterion_points")]
+    pub points: u32,
+}
+
+fn default_criterion_points() -> u32 {
+    1
+}
+
+/// Represents a learner's answer to an exercise.
+#[derive(Debug, Deserialize)]
+pub struct ExerciseSubmission {
+    /// The learner's answer
+    pub answer: String,
+}
+
+/// Represents the verdict returned by the AI when grading an exercise.
+#[derive(Debug, Deserialize)]
+pub struct ExerciseVerdict {
+    /// IDs of the rubric criteria the answer meets
+    pub met_criteria: Vec<String>,
+    /// Feedback for the learner
+    pub feedback: String,
+    /// Points the answer is missing, phrased for the learner
+    #[serde(default)]
+    pub missing_points: Vec<String>,
+}
+
+/// Represents the graded result of an exercise submission.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ExerciseResult {
+    /// Fraction of rubric points awarded (0.0 to 1.0)
+    pub score: f32,
+    /// Whether the score reached the exercise's pass score
+    pub passed: bool,
+    /// IDs of the rubric criteria the answer meets
+    pub met_criteria: Vec<String>,
+    /// Feedback for the learner
+    pub feedback: String,
+    /// Points the answer is missing
+    pub missing_points: Vec<String>,
+    /// When the answer was graded
+    pub graded_at: DateTime<Utc>,
+    /// Warnings for the learner about their answer, such as secrets removed before grading
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+/// Represents a quiz attached to a step.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Quiz {
+    /// The questions of the quiz
+    pub questions: Vec<QuizQuestion>,
+    /// Fraction of questions that must be answered correctly to pass (0.0 to 1.0)
+    #[serde(default = "default_pass_score")]
+    pub pass_score: f32,
+}
+
+fn default_pass_score() -> f32 {
+    0.7
+}
+
+/// Represents a single quiz question.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QuizQuestion {
+    /// Identifier of the question, unique within the quiz
+    pub id: String,
+    /// The question text
+    pub prompt: String,
+    /// The type of the question and its answer key
+    #[serde(flatten)]
+    pub kind: QuizQuestionKind,
+}
+
+/// Represents the type of a quiz question.
+///
+/// Answer keys are stored server-side only and are emptied before a topic is
+/// served to learners.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum QuizQuestionKind {
+    /// A question answered by selecting one or more options
+    MultipleChoice {
+        /// The options to choose from
+        options: Vec<String>,
+        /// Indices of the correct options
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        correct_options: Vec<usize>,
+    },
+    /// A question answered with a short free-text answer
+    ShortAnswer {
+        /// Answers accepted as correct, compared ignoring case, whitespace and trailing punctuation
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        accepted_answers: Vec<String>,
+    },
+}
+
+/// Represents a learner's answers to a quiz.
+#[derive(Debug, Deserialize)]
+pub struct QuizSubmission {
+    /// The answers, one per question
+    pub answers: Vec<QuizAnswer>,
+}
+
+/// Represents a learner's answer to a single quiz question.
+#[derive(Debug, Deserialize)]
+pub struct QuizAnswer {
+    /// The ID of the question being answered
+    pub question_id: String,
+    /// Selected option indices, for multiple-choice questions
+    #[serde(default)]
+    pub selected_options: Vec<usize>,
+    /// Free-text answer, for short-answer questions
+    #[serde(default)]
+    pub answer: Option<String>,
+}
+
+/// Represents the graded result of a quiz submission.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QuizResult {
+    /// Fraction of questions answered correctly (0.0 to 1.0)
+    pub score: f32,
+    /// Whether the score reached the quiz's pass score
+    pub passed: bool,
+    /// Per-question results
+    pub questions: Vec<QuizQuestionResult>,
+    /// When the quiz was graded
+    pub graded_at: DateTime<Utc>,
+}
+
+/// Represents the result of a single quiz question.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QuizQuestionResult {
+    /// The ID of the question
+    pub question_id: String,
+    /// Whether the question was answered correctly
+    pub correct: bool,
 }
 
 /// Represents a generic response structure for API calls.
@@ -38,6 +293,24 @@ pub struct GenericResponse {
     pub message: String,
 }
 
+/// Represents an error response listing validation problems.
+#[derive(Debug, Serialize)]
+pub struct ValidationErrorResponse {
+    /// HTTP status code
+    pub status: u16,
+    /// Response message
+    pub message: String,
+    /// Individual validation errors
+    pub errors: Vec<String>,
+}
+
+/// Represents the learner's preferences.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Preferences {
+    /// Preferred locale for topic content and AI responses (e.g., "es")
+    pub locale: Option<String>,
+}
+
 /// Represents an update to the user's progress on a topic.
 #[derive(Debug, Deserialize)]
 pub struct ProgressUpdate {
@@ -52,12 +325,36 @@ pub struct ProgressUpdate {
 pub struct Progress {
     /// The ID of the topic
     pub topic_id: String,
-    /// List of completed step indices
+    /// The version of the topic this progress is pinned to
+    #[serde(default = "default_topic_version")]
+    pub topic_version: u32,
+    /// List of completed step indices, relative to the pinned topic version
     pub completed_steps: Vec<usize>,
+    /// List of completed step IDs
+    #[serde(default)]
+    pub completed_step_ids: Vec<String>,
     /// The current step the user is on
     pub current_step: usize,
 }
 
+impl Progress {
+    /// Creates empty progress pinned to the given topic version.
+    pub fn new(topic_id: &str, topic_version: u32) -> Self {
+        Progress {
+            topic_id: topic_id.to_string(),
+            topic_version,
+            completed_steps: vec![],
+            completed_step_ids: vec![],
+            current_step: 0,
+        }
+    }
+}
+
+/// Progress stored before topics were versioned refers to the first version.
+fn default_topic_version() -> u32 {
+    1
+}
+
 /// Represents the overall conversation history for a specific topic.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ConversationHistory {
@@ -65,17 +362,171 @@ pub struct ConversationHistory {
     pub topic_id: String,
     /// List of messages in the conversation
     pub messages: Vec<TimestampedChatMessage>,
+    /// Counter used to assign unique message IDs
+    #[serde(default)]
+    pub next_message_id: u64,
+}
+
+/// Represents a page of messages from a conversation.
+#[derive(Debug, Serialize)]
+pub struct ConversationPage {
+    /// The ID of the topic this conversation is associated with
+    pub topic_id: String,
+    /// The messages of the page, oldest first
+    pub messages: Vec<TimestampedChatMessage>,
+    /// Whether more messages exist beyond this page, in the direction it was fetched
+    pub has_more: bool,
 }
 
 /// Represents a single message in the conversation, with a timestamp.
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TimestampedChatMessage {
+    /// Unique identifier of the message within the conversation
+    #[serde(default)]
+    pub id: String,
     /// The role of the message sender (e.g., "user" or "assistant")
     pub role: String,
     /// The content of the message
     pub content: String,
     /// The timestamp when the message was sent or received
     pub timestamp: DateTime<Utc>,
+    /// The ID of the step the learner was on when the message was sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub step_id: Option<String>,
+    /// Files the learner attached to the message
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<Attachment>,
+    /// Earlier versions of the message, replaced by regenerating or editing it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alternates: Vec<MessageAlternate>,
+    /// For assistant messages, the prompt template version used to generate it (e.g., "tutor-system@2")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_version: Option<String>,
+}
+
+/// Represents a file attached to a learner message.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Attachment {
+    /// The file name (e.g., "error.png" or "Dockerfile")
+    pub name: String,
+    /// The media type (e.g., "image/png" or "text/plain")
+    pub media_type: String,
+    /// The contents of the file: base64-encoded for images, plain text for text files.
+    /// Empty on stored messages, whose attachment contents are stored under their own keys.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub data: String,
+    /// The size of the contents in bytes, set by the server
+    #[serde(default)]
+    pub size: usize,
+}
+
+/// Represents a replaced version of a message.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MessageAlternate {
+    /// The content of the replaced version
+    pub content: String,
+    /// The timestamp of the replaced version
+    pub timestamp: DateTime<Utc>,
+    /// For assistant messages, the prompt template version used to generate the replaced version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_version: Option<String>,
+    /// For user messages, the replies that followed the replaced version
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub replies: Vec<TimestampedChatMessage>,
+}
+
+/// Represents a portable archive of a conversation, as exported for learners to keep.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ConversationArchive {
+    /// Version of the archive format
+    pub format_version: u32,
+    /// When the archive was exported
+    pub exported_at: DateTime<Utc>,
+    /// The ID of the topic the conversation is associated with
+    pub topic_id: String,
+    /// The title of the topic at the time of export
+    pub topic_title: String,
+    /// The topic version the learner's progress was pinned to
+    pub topic_version: u32,
+    /// The messages of the conversation
+    pub messages: Vec<TimestampedChatMessage>,
+}
+
+/// Represents a conversation restored from an archive, kept apart from the topic's conversation.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ConversationThread {
+    /// The ID of the thread within its topic
+    pub id: String,
+    /// When the archive was imported
+    pub imported_at: DateTime<Utc>,
+    /// The topic version the archived conversation was pinned to
+    pub topic_version: u32,
+    /// The restored conversation
+    pub conversation: ConversationHistory,
+}
+
+/// Represents an imported thread in the list of a topic's threads.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ThreadSummary {
+    /// The ID of the thread within its topic
+    pub id: String,
+    /// When the archive was imported
+    pub imported_at: DateTime<Utc>,
+    /// The number of messages in the thread
+    pub message_count: usize,
+}
+
+/// Represents the formats a conversation can be exported in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Markdown,
+    Html,
+    Json,
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "markdown" | "md" => Ok(ExportFormat::Markdown),
+            "html" => Ok(ExportFormat::Html),
+            "json" => Ok(ExportFormat::Json),
+            _ => Err(format!("Unknown export format: {}", s)),
+        }
+    }
+}
+
+/// Represents a message matching a search of the learner's conversations.
+#[derive(Debug, Serialize, Clone)]
+pub struct ConversationSearchResult {
+    /// The ID of the topic the conversation belongs to
+    pub topic_id: String,
+    /// The title of the topic
+    pub topic_title: String,
+    /// The ID of the imported thread the message belongs to, if it is not in the topic's conversation
+    pub thread_id: Option<String>,
+    /// The ID of the matching message
+    pub message_id: String,
+    /// The position of the message in the conversation, starting at 0
+    pub position: usize,
+    /// The role of the message sender ("user" or "assistant")
+    pub role: String,
+    /// The ID of the step the message was sent on, if known
+    pub step_id: Option<String>,
+    /// The timestamp of the message
+    pub timestamp: DateTime<Utc>,
+    /// An excerpt of the message with the matching terms in bold
+    pub snippet: String,
+    /// The relevance of the match; higher is better
+    pub score: usize,
+}
+
+/// Represents a request to switch a message to one of its alternates.
+#[derive(Debug, Deserialize)]
+pub struct SelectAlternateRequest {
+    /// Index of the alternate to switch to
+    pub alternate: usize,
 }
 
 /// Represents a chat message sent by the user.
@@ -83,15 +534,262 @@ pub struct TimestampedChatMessage {
 pub struct ChatMessage {
     /// The content of the message
     pub message: String,
+    /// Screenshots and text files attached to the message
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
 }
 
 /// Represents the response to a chat message.
 #[derive(Debug, Serialize)]
 pub struct ChatResponse {
+    /// The ID of the assistant message holding the response
+    pub message_id: String,
     /// The AI-generated response
     pub response: String,
-    /// Suggested questions based on the current step
+    /// Suggested follow-up questions, combining AI-generated questions with those of the current step
     pub suggested_questions: Vec<String>,
+    /// The documentation excerpts cited in the response
+    pub citations: Vec<Citation>,
+    /// Warnings for the learner raised by the guardrails
+    pub warnings: Vec<String>,
+    /// The names of the tools the tutor called while generating the response, in order
+    pub tool_calls: Vec<String>,
+}
+
+/// Represents a documentation excerpt cited in a response.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct Citation {
+    /// The number the response refers to the excerpt by, e.g. `[1]`
+    pub index: usize,
+    /// The ID of the source document
+    pub document_id: String,
+    /// The title of the source document
+    pub title: String,
+    /// The heading of the section the excerpt comes from
+    pub heading: String,
+    /// Where the source document can be read
+    pub source_url: String,
+}
+
+/// Represents a curated documentation page ingested into the retrieval corpus.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SourceDocument {
+    /// Unique identifier for the document
+    pub id: String,
+    /// The title of the document
+    pub title: String,
+    /// Where the original document can be read
+    pub source_url: String,
+    /// The document content, in markdown
+    pub markdown: String,
+}
+
+/// Represents a section of a source document, the unit of retrieval.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DocChunk {
+    /// Unique identifier for the chunk, `{document_id}#{n}`
+    pub id: String,
+    /// The ID of the source document
+    pub document_id: String,
+    /// The title of the source document
+    pub title: String,
+    /// The heading of the section the chunk comes from
+    pub heading: String,
+    /// Where the source document can be read
+    pub source_url: String,
+    /// The content of the chunk, in markdown
+    pub content: String,
+}
+
+/// Represents a chunk together with the term statistics used to rank it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IndexedChunk {
+    /// The chunk
+    pub chunk: DocChunk,
+    /// Number of occurrences of each term in the chunk
+    pub term_frequencies: HashMap<String, u32>,
+    /// Total number of terms in the chunk
+    pub length: u32,
+}
+
+/// Represents the BM25 index of the documentation corpus.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DocIndex {
+    /// The indexed chunks
+    pub chunks: Vec<IndexedChunk>,
+    /// Number of chunks each term appears in
+    pub document_frequencies: HashMap<String, u32>,
+    /// Average number of terms in a chunk
+    pub average_length: f32,
+}
+
+/// Represents a learner's rating of an assistant message.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Rating {
+    Up,
+    Down,
+}
+
+/// Represents the reason given for rating an assistant message.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FeedbackCategory {
+    Incorrect,
+    Unclear,
+    OffTopic,
+    Other,
+}
+
+/// Represents feedback submitted by the learner on an assistant message.
+#[derive(Debug, Deserialize)]
+pub struct FeedbackRequest {
+    /// Thumbs up or down
+    pub rating: Rating,
+    /// Reason for the rating
+    pub category: Option<FeedbackCategory>,
+    /// Free-text comment
+    pub comment: Option<String>,
+}
+
+/// Represents stored feedback on an assistant message, kept for instructor review.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MessageFeedback {
+    /// The ID of the rated message
+    pub message_id: String,
+    /// The ID of the topic the conversation belongs to
+    pub topic_id: String,
+    /// The ID of the step the learner was on when the message was sent
+    pub step_id: Option<String>,
+    /// Thumbs up or down
+    pub rating: Rating,
+    /// Reason for the rating
+    pub category: Option<FeedbackCategory>,
+    /// Free-text comment
+    pub comment: Option<String>,
+    /// The content of the rated message
+    pub message_content: String,
+    /// When the feedback was submitted
+    pub created_at: DateTime<Utc>,
+}
+
+/// Represents the kinds of problem a guardrail rule detects.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum GuardrailCategory {
+    OffTopic,
+    PromptInjection,
+    Secret,
+    Abuse,
+}
+
+/// Represents the stage of a chat exchange a guardrail rule applies to.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum GuardrailStage {
+    /// Messages sent by the learner, before they reach the model
+    Inbound,
+    /// Replies generated by the model, before they reach the learner
+    Outbound,
+}
+
+/// Represents what happens when a guardrail rule is triggered.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum GuardrailAction {
+    /// Reject the message, or replace the reply with a refusal
+    Block,
+    /// Replace the matching text with a placeholder
+    Redact,
+    /// Let the text through and warn the learner
+    Warn,
+}
+
+/// Represents a configurable guardrail rule.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GuardrailRule {
+    /// Unique identifier for the rule
+    pub id: String,
+    /// The kind of problem the rule detects
+    pub category: GuardrailCategory,
+    /// The stages the rule applies to
+    pub stages: Vec<GuardrailStage>,
+    /// What happens when the rule is triggered
+    pub action: GuardrailAction,
+    /// Phrases matched as whole words, ignoring case
+    #[serde(default)]
+    pub phrases: Vec<String>,
+    /// Prefixes matched at the start of a word, respecting case (e.g. `ghp_`)
+    #[serde(default)]
+    pub token_prefixes: Vec<String>,
+    /// The message shown to the learner when the rule blocks or warns
+    pub message: String,
+}
+
+/// Represents the set of guardrail rules applied around the model.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GuardrailPolicy {
+    /// The rules, applied in order
+    pub rules: Vec<GuardrailRule>,
+}
+
+/// Represents a triggered guardrail rule, recorded for review.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GuardrailAuditEntry {
+    /// The ID of the triggered rule
+    pub rule_id: String,
+    /// The kind of problem detected
+    pub category: GuardrailCategory,
+    /// The action taken
+    pub action: GuardrailAction,
+    /// The stage the rule was triggered at
+    pub stage: GuardrailStage,
+    /// The ID of the topic the conversation belongs to
+    pub topic_id: String,
+    /// When the rule was triggered
+    pub created_at: DateTime<Utc>,
+}
+
+/// Represents a named, versioned prompt template.
+#[derive(Debug, Serialize, Clone)]
+pub struct PromptTemplate {
+    /// Name of the template (e.g., "tutor-system")
+    pub name: String,
+    /// Version of the template
+    pub version: u32,
+    /// What the version changes, for admins comparing versions
+    pub description: String,
+    /// Body of the template, with `{{variable}}` placeholders and `{{#if variable}}` blocks
+    pub body: String,
+}
+
+/// Represents an A/B experiment assigning learners to versions of a prompt template.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PromptExperiment {
+    /// Name of the template the experiment applies to
+    pub template: String,
+    /// The versions compared, with their relative weights
+    pub variants: Vec<PromptVariant>,
+}
+
+/// Represents a version of a prompt template taking part in an experiment.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PromptVariant {
+    /// Version of the template
+    pub version: u32,
+    /// Relative weight of the version when assigning learners
+    pub weight: u32,
+}
+
+/// Represents the template version a learner has been assigned to.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PromptAssignment {
+    /// Name of the template
+    pub template: String,
+    /// The assigned version
+    pub version: u32,
+    /// When the learner was assigned
+    pub assigned_at: DateTime<Utc>,
 }
 
 /// Represents a request to the Claude API.
@@ -103,27 +801,184 @@ pub struct ClaudeRequest {
     pub max_tokens: u32,
     /// The conversation history and new message
     pub messages: Vec<ClaudeMessage>,
-    /// The system prompt to set the context for the conversation
-    pub system: Option<String>,
+    /// The blocks of the system prompt, setting the context for the conversation
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub system: Vec<ClaudeContentBlock>,
+    /// The tools the model may call
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<ClaudeTool>,
+    /// How the model may use the tools; the model decides when not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ClaudeToolChoice>,
+}
+
+/// Represents how the model may use the tools of a request.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct ClaudeToolChoice {
+    /// The type of choice (e.g., "auto" or "none")
+    #[serde(rename = "type")]
+    pub choice_type: String,
+}
+
+impl ClaudeToolChoice {
+    /// Forbids the model from calling tools.
+    pub fn none() -> Self {
+        ClaudeToolChoice { choice_type: "none".to_string() }
+    }
+}
+
+/// Represents a tool the model may call.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct ClaudeTool {
+    /// The name of the tool
+    pub name: String,
+    /// What the tool does and when to call it
+    pub description: String,
+    /// The JSON schema of the tool's arguments
+    pub input_schema: serde_json::Value,
 }
 
 /// Represents a single message in the Claude API request.
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Clone)]
 pub struct ClaudeMessage {
     /// The role of the message sender (e.g., "user" or "assistant")
     pub role: String,
-    /// The content of the message
-    pub content: String,
+    /// The content blocks of the message
+    pub content: Vec<ClaudeContentBlock>,
     /// The name of the message sender (optional)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
 }
 
+impl ClaudeMessage {
+    /// Returns the text of the message, joining its text blocks.
+    pub fn text(&self) -> String {
+        join_text(&self.content)
+    }
+}
+
+/// Joins the text blocks of a list of content blocks, skipping the others,
+/// including blocks of unknown kinds.
+pub fn join_text(blocks: &[ClaudeContentBlock]) -> String {
+    blocks.iter().filter_map(ClaudeContentBlock::as_text).collect::<Vec<_>>().join("\n\n")
+}
+
+/// Represents a content block of a Claude API request or response.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClaudeContentBlock {
+    /// A block of text
+    Text {
+        /// The text of the block
+        text: String,
+        /// Marks the end of a prefix of the request the API should cache
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    /// An image, sent by the learner
+    Image {
+        /// The encoded image
+        source: ClaudeImageSource,
+        /// Marks the end of a prefix of the request the API should cache
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    /// A call to a tool, made by the model
+    ToolUse {
+        /// Unique identifier of the call, referenced by its result
+        id: String,
+        /// The name of the tool
+        name: String,
+        /// The arguments of the call
+        input: serde_json::Value,
+    },
+    /// The result of a call to a tool, sent back to the model
+    ToolResult {
+        /// The identifier of the call this is the result of
+        tool_use_id: String,
+        /// The blocks of the result
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        content: Vec<ClaudeContentBlock>,
+        /// Whether the call failed
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        is_error: Option<bool>,
+    },
+    /// A kind of block this client does not know, such as blocks of newer API features
+    #[serde(other)]
+    Unknown,
+}
+
+impl ClaudeContentBlock {
+    /// Creates a text block that is not a cache breakpoint.
+    pub fn text(text: impl Into<String>) -> Self {
+        ClaudeContentBlock::Text {
+            text: text.into(),
+            cache_control: None,
+        }
+    }
+
+    /// Creates a text block ending a prefix of the request the API should cache.
+    pub fn cached_text(text: impl Into<String>) -> Self {
+        ClaudeContentBlock::Text {
+            text: text.into(),
+            cache_control: Some(CacheControl { cache_type: "ephemeral".to_string() }),
+        }
+    }
+
+    /// Creates an image block from base64-encoded data.
+    pub fn image(media_type: impl Into<String>, data: impl Into<String>) -> Self {
+        ClaudeContentBlock::Image {
+            source: ClaudeImageSource {
+                source_type: "base64".to_string(),
+                media_type: media_type.into(),
+                data: data.into(),
+            },
+            cache_control: None,
+        }
+    }
+
+    /// Returns the text of a text block, or `None` for other blocks.
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            ClaudeContentBlock::Text { text, .. } => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Returns the cache breakpoint of the block, if any.
+    pub fn cache_control(&self) -> Option<&CacheControl> {
+        match self {
+            ClaudeContentBlock::Text { cache_control, .. } | ClaudeContentBlock::Image { cache_control, .. } => cache_control.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+/// Represents the source of an image block.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ClaudeImageSource {
+    /// The type of the source, always "base64"
+    #[serde(rename = "type")]
+    pub source_type: String,
+    /// The media type of the image (e.g., "image/png")
+    pub media_type: String,
+    /// The base64-encoded image
+    pub data: String,
+}
+
+/// Represents a prompt caching breakpoint.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CacheControl {
+    /// The type of cache, always "ephemeral"
+    #[serde(rename = "type")]
+    pub cache_type: String,
+}
+
 /// Represents the response from the Claude API.
 #[derive(Debug, Deserialize)]
 pub struct ClaudeResponse {
-    /// The generated content
-    pub content: Vec<ClaudeContent>,
+    /// The generated content blocks
+    pub content: Vec<ClaudeContentBlock>,
     /// Unique identifier for the response
     pub id: String,
     /// The model used for generation
@@ -141,21 +996,48 @@ pub struct ClaudeResponse {
     pub usage: ClaudeUsage,
 }
 
-/// Represents the content of a Claude API response.
-#[derive(Debug, Deserialize)]
-pub struct ClaudeContent {
-    /// The generated text
-    pub text: String,
-    /// The type of the content
-    #[serde(rename = "type")]
-    pub content_type: String,
+impl ClaudeResponse {
+    /// Returns the text of the response, joining its text blocks.
+    pub fn text(&self) -> String {
+        join_text(&self.content)
+    }
 }
 
 /// Represents the usage statistics for a Claude API call.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone, Default)]
 pub struct ClaudeUsage {
-    /// Number of input tokens
+    /// Number of input tokens, excluding tokens read from or written to the cache
     pub input_tokens: u32,
     /// Number of output tokens
     pub output_tokens: u32,
+    /// Number of input tokens written to the prompt cache
+    #[serde(default)]
+    pub cache_creation_input_tokens: Option<u32>,
+    /// Number of input tokens read from the prompt cache
+    #[serde(default)]
+    pub cache_read_input_tokens: Option<u32>,
+}
+
+/// Represents the tokens used by the calls to the Claude API on one day, by purpose (e.g., "chat").
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UsageReport {
+    /// The day, as YYYY-MM-DD in UTC
+    pub date: String,
+    /// The totals of each purpose
+    pub purposes: HashMap<String, UsageTotals>,
+}
+
+/// Represents the tokens used by the calls to the Claude API made for one purpose on one day.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct UsageTotals {
+    /// Number of API calls
+    pub requests: u32,
+    /// Number of input tokens, excluding tokens read from or written to the cache
+    pub input_tokens: u64,
+    /// Number of output tokens
+    pub output_tokens: u64,
+    /// Number of input tokens written to the prompt cache
+    pub cache_creation_input_tokens: u64,
+    /// Number of input tokens read from the prompt cache
+    pub cache_read_input_tokens: u64,
 }
@@ -0,0 +1,98 @@
+//! This module keeps account of the tokens used by calls to the Claude API.
+//!
+//! Totals are kept per day and per purpose, such as chat replies or exercise
+//! grading, and include the tokens written to and read from the prompt cache
+//! so its effect on cost can be followed.
+
+use std::collections::HashMap;
+use chrono::{NaiveDate, Utc};
+use worker::*;
+use crate::types::{ClaudeUsage, UsageReport, UsageTotals};
+
+/// Purpose of the calls generating chat replies.
+pub const CHAT: &str = "chat";
+/// Purpose of the calls grading exercise answers.
+pub const GRADING: &str = "grading";
+
+/// Returns the key of the KV entry holding the usage of a day.
+fn usage_key(date: NaiveDate) -> String {
+    format!("usage_{}", date.format("%Y-%m-%d"))
+}
+
+/// Loads the usage of a day.
+pub async fn load_usage(kv: &kv::KvStore, date: NaiveDate) -> Result<UsageReport> {
+    let purposes: HashMap<String, UsageTotals> = kv.get(&usage_key(date)).json().await?.unwrap_or_default();
+    Ok(UsageReport {
+        date: date.format("%Y-%m-%d").to_string(),
+        purposes,
+    })
+}
+
+/// Adds the usage of API calls to today's totals for their purpose.
+///
+/// # Arguments
+///
+/// * `kv` - The data store
+/// * `purpose` - The purpose of the calls, such as `CHAT`
+/// * `usage` - The usage of the calls, summed
+/// * `requests` - The number of calls, more than one when the model called tools
+pub async fn record_usage(kv: &kv::KvStore, purpose: &str, usage: &ClaudeUsage, requests: u32) -> Result<()> {
+    let today = Utc::now().date_naive();
+    let mut report = load_usage(kv, today).await?;
+    add_usage(report.purposes.entry(purpose.to_string()).or_default(), usage, requests);
+
+    console_log!(
+        "Claude API {} calls ({}) used {} input, {} output, {} cache write and {} cache read tokens",
+        purpose,
+        requests,
+        usage.input_tokens,
+        usage.output_tokens,
+        usage.cache_creation_input_tokens.unwrap_or(0),
+        usage.cache_read_input_tokens.unwrap_or(0)
+    );
+
+    kv.put(&usage_key(today), serde_json::to_string(&report.purposes)?)?
+        .execute().await?;
+    Ok(())
+}
+
+/// Adds the usage of API calls to totals.
+fn add_usage(totals: &mut UsageTotals, usage: &ClaudeUsage, requests: u32) {
+    totals.requests += requests;
+    totals.input_tokens += u64::from(usage.input_tokens);
+    totals.output_tokens += u64::from(usage.output_tokens);
+    totals.cache_creation_input_tokens += u64::from(usage.cache_creation_input_tokens.unwrap_or(0));
+    totals.cache_read_input_tokens += u64::from(usage.cache_read_input_tokens.unwrap_or(0));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_usage() {
+        let mut totals = UsageTotals::default();
+
+        add_usage(&mut totals, &ClaudeUsage {
+            input_tokens: 40,
+            output_tokens: 200,
+            cache_creation_input_tokens: Some(1800),
+            cache_read_input_tokens: None,
+        }, 1);
+        add_usage(&mut totals, &ClaudeUsage {
+            input_tokens: 60,
+            output_tokens: 150,
+            cache_creation_input_tokens: Some(0),
+            cache_read_input_tokens: Some(1800),
+        }, 3);
+
+        assert_eq!(totals, UsageTotals {
+            requests: 4,
+            input_tokens: 100,
+            output_tokens: 350,
+            cache_creation_input_tokens: 1800,
+            cache_read_input_tokens: 1800,
+        });
+        assert_eq!(usage_key(NaiveDate::from_ymd_opt(2024, 3, 9).unwrap()), "usage_2024-03-09");
+    }
+}
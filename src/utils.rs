@@ -12,8 +12,8 @@ use worker::*;
 pub fn handle_cors_preflight() -> Result<Response> {
     let mut headers = Headers::new();
     headers.set("Access-Control-Allow-Origin", "https://devops-ai-react.pages.dev")?;
-    headers.set("Access-Control-Allow-Methods", "GET, POST, OPTIONS")?;
-    headers.set("Access-Control-Allow-Headers", "Content-Type")?;
+    headers.set("Access-Control-Allow-Methods", "GET, POST, PUT, DELETE, OPTIONS")?;
+    headers.set("Access-Control-Allow-Headers", "Content-Type, Authorization, X-Learner-Id")?;
     headers.set("Access-Control-Max-Age", "86400")?;
     
     Ok(Response::ok("").unwrap().with_headers(headers))
@@ -35,9 +35,53 @@ pub fn add_cors_headers(res: &mut Response) {
         .set("Access-Control-Allow-Origin", "https://devops-ai-react.pages.dev")
         .expect("Failed to set Access-Control-Allow-Origin header");
     res.headers_mut()
-        .set("Access-Control-Allow-Methods", "GET, POST, OPTIONS")
+        .set("Access-Control-Allow-Methods", "GET, POST, PUT, DELETE, OPTIONS")
         .expect("Failed to set Access-Control-Allow-Methods header");
     res.headers_mut()
-        .set("Access-Control-Allow-Headers", "Content-Type")
+        .set("Access-Control-Allow-Headers", "Content-Type, Authorization, X-Learner-Id")
         .expect("Failed to set Access-Control-Allow-Headers header");
 }
+
+/// Checks whether a request carries the admin API key.
+///
+/// The key is expected as a bearer token in the `Authorization` header and is
+/// compared against the `ADMIN_API_KEY` secret. An empty secret disables admin access.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request
+/// * `ctx` - The route context used to read the admin secret
+///
+/// # Returns
+///
+/// A `Result<bool>` indicating whether the request is authorized.
+pub fn is_admin(req: &Request, ctx: &RouteContext<()>) -> Result<bool> {
+    let admin_key = ctx.secret("ADMIN_API_KEY")?.to_string();
+    if admin_key.is_empty() {
+        return Ok(false);
+    }
+
+    let provided = req.headers().get("Authorization")?.unwrap_or_default();
+    let token = provided.strip_prefix("Bearer ").unwrap_or("");
+
+    // Compare every byte so the comparison time does not leak the key
+    Ok(token.len() == admin_key.len()
+        && token.bytes().zip(admin_key.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0)
+}
+
+/// Returns the learner's identifier, sent by the frontend in the `X-Learner-Id` header.
+///
+/// Identifiers are 1 to 64 ASCII letters, digits, dashes or underscores; anything
+/// else is ignored, as if no identifier was sent.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request
+///
+/// # Returns
+///
+/// A `Result<Option<String>>` containing the identifier, if a valid one was sent.
+pub fn learner_id(req: &Request) -> Result<Option<String>> {
+    Ok(req.headers().get("X-Learner-Id")?
+        .filter(|id| (1..=64).contains(&id.len()) && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')))
+}
@@ -0,0 +1,433 @@
+//! This module contains the topic content linter.
+//!
+//! It checks topic documents for schema and content problems and produces a
+//! report pointing at the exact field of every problem found. It is used when
+//! topics are authored or loaded, by the unit tests, and by the `lint-topics` binary.
+
+use std::fmt;
+use crate::i18n;
+use crate::tools;
+use crate::types::{Exercise, Quiz, QuizQuestionKind, Step, Topic};
+
+/// Maximum length of a step prompt, in characters.
+pub const MAX_PROMPT_LENGTH: usize = 2000;
+/// Maximum length of a topic or step title, in characters.
+pub const MAX_TITLE_LENGTH: usize = 120;
+/// Minimum number of suggested questions per step.
+pub const MIN_SUGGESTED_QUESTIONS: usize = 1;
+/// Maximum number of suggested questions per step.
+pub const MAX_SUGGESTED_QUESTIONS: usize = 5;
+/// Topic ids whose KV keys would collide with other entries, such as `topic_index`.
+pub const RESERVED_TOPIC_IDS: &[&str] = &["index"];
+
+/// Represents a single problem found in a topic.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationIssue {
+    /// Path of the offending field, e.g. `steps[2].prompt`
+    pub path: String,
+    /// Description of the problem
+    pub message: String,
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// Represents all problems found in a topic.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationReport {
+    /// The ID of the topic that failed validation
+    pub topic_id: String,
+    /// The problems found
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    /// Returns the issues formatted as `path: message` strings.
+    pub fn messages(&self) -> Vec<String> {
+        self.issues.iter().map(|issue| issue.to_string()).collect()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Topic '{}' has {} problem(s):", self.topic_id, self.issues.len())?;
+        for issue in &self.issues {
+            writeln!(f, "  - {}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+/// Collects issues while walking a topic.
+struct Linter {
+    issues: Vec<ValidationIssue>,
+}
+
+impl Linter {
+    fn report(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.issues.push(ValidationIssue {
+            path: path.into(),
+            message: message.into(),
+        });
+    }
+
+    fn check_title(&mut self, path: &str, title: &str) {
+        if title.trim().is_empty() {
+            self.report(path, "must not be empty");
+        } else if title.chars().count() > MAX_TITLE_LENGTH {
+            self.report(path, format!("must be at most {} characters", MAX_TITLE_LENGTH));
+        }
+    }
+}
+
+/// Validates a topic document.
+///
+/// # Arguments
+///
+/// * `topic` - The topic to validate
+/// * `known_topic_ids` - IDs of all topics in the catalogue, used to check prerequisites
+///
+/// # Returns
+///
+/// `Ok(())` if the topic is valid, or a report of every problem found.
+pub fn validate_topic(topic: &Topic, known_topic_ids: &[String]) -> Result<(), ValidationReport> {
+    let mut linter = Linter { issues: vec![] };
+
+    if topic.id.is_empty() || !topic.id.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-') {
+        linter.report("id", "must be non-empty and contain only lowercase letters, digits and dashes");
+    } else if RESERVED_TOPIC_IDS.contains(&topic.id.as_str()) {
+        linter.report("id", format!("'{}' is reserved", topic.id));
+    }
+    linter.check_title("title", &topic.title);
+    if topic.description.trim().is_empty() {
+        linter.report("description", "must not be empty");
+    }
+    if topic.initial_message.trim().is_empty() {
+        linter.report("initial_message", "must not be empty");
+    }
+    for problem in markdown_problems(&topic.initial_message) {
+        linter.report("initial_message", problem);
+    }
+
+    for (index, prerequisite) in topic.prerequisites.iter().enumerate() {
+        let path = format!("prerequisites[{}]", index);
+        if prerequisite == &topic.id {
+            linter.report(path, "topic cannot be its own prerequisite");
+        } else if !known_topic_ids.contains(prerequisite) {
+            linter.report(path, format!("unknown topic '{}'", prerequisite));
+        }
+    }
+
+    for (index, tool) in topic.tools.iter().flatten().enumerate() {
+        if !tools::TOOL_NAMES.contains(&tool.as_str()) {
+            linter.report(format!("tools[{}]", index), format!("unknown tool '{}'", tool));
+        }
+    }
+
+    let mut locales: Vec<&String> = topic.translations.keys().collect();
+    locales.sort();
+    for locale in locales {
+        let translation = &topic.translations[locale];
+        let path = format!("translations.{}", locale);
+        if i18n::normalize_locale(locale) != Some(locale.as_str()) || locale == i18n::DEFAULT_LOCALE {
+            linter.report(path.clone(), "unsupported locale");
+        }
+        if let Some(initial_message) = &translation.initial_message {
+            for problem in markdown_problems(initial_message) {
+                linter.report(format!("{}.initial_message", path), problem);
+            }
+        }
+        let mut step_ids: Vec<&String> = translation.steps.keys().collect();
+        step_ids.sort();
+        for step_id in step_ids {
+            if !topic.steps.iter().any(|step| &step.id == step_id) {
+                linter.report(format!("{}.steps.{}", path, step_id), "unknown step id");
+            }
+            let questions = translation.steps[step_id].suggested_questions.as_ref().map_or(1, |q| q.len());
+            if !(MIN_SUGGESTED_QUESTIONS..=MAX_SUGGESTED_QUESTIONS).contains(&questions) {
+                linter.report(
+                    format!("{}.steps.{}.suggested_questions", path, step_id),
+                    format!("must contain between {} and {} questions, found {}", MIN_SUGGESTED_QUESTIONS, MAX_SUGGESTED_QUESTIONS, questions),
+                );
+            }
+        }
+    }
+
+    if topic.steps.is_empty() {
+        linter.report("steps", "topic must have at least one step");
+    }
+    for (index, step) in topic.steps.iter().enumerate() {
+        lint_step(&mut linter, index, step);
+
+        let earlier = &topic.steps[..index];
+        if !step.id.is_empty() && earlier.iter().any(|other| other.id == step.id) {
+            linter.report(format!("steps[{}].id", index), format!("duplicate step id '{}'", step.id));
+        }
+        if earlier.iter().any(|other| other.title.trim().eq_ignore_ascii_case(step.title.trim())) {
+            linter.report(format!("steps[{}].title", index), format!("duplicate step title '{}'", step.title));
+        }
+    }
+
+    if linter.issues.is_empty() {
+        Ok(())
+    } else {
+        Err(ValidationReport {
+            topic_id: topic.id.clone(),
+            issues: linter.issues,
+        })
+    }
+}
+
+fn lint_step(linter: &mut Linter, index: usize, step: &Step) {
+    let path = |field: &str| format!("steps[{}].{}", index, field);
+
+    if step.id.is_empty() {
+        linter.report(path("id"), "must not be empty");
+    }
+    linter.check_title(&path("title"), &step.title);
+
+    if step.prompt.trim().is_empty() {
+        linter.report(path("prompt"), "must not be empty");
+    } else if step.prompt.chars().count() > MAX_PROMPT_LENGTH {
+        linter.report(path("prompt"), format!("must be at most {} characters", MAX_PROMPT_LENGTH));
+    }
+
+    let question_count = step.suggested_questions.len();
+    if !(MIN_SUGGESTED_QUESTIONS..=MAX_SUGGESTED_QUESTIONS).contains(&question_count) {
+        linter.report(
+            path("suggested_questions"),
+            format!("must contain between {} and {} questions, found {}", MIN_SUGGESTED_QUESTIONS, MAX_SUGGESTED_QUESTIONS, question_count),
+        );
+    }
+    for (question_index, question) in step.suggested_questions.iter().enumerate() {
+        if question.trim().is_empty() {
+            linter.report(format!("steps[{}].suggested_questions[{}]", index, question_index), "must not be empty");
+        }
+    }
+
+    if let Some(quiz) = &step.quiz {
+        lint_quiz(linter, &path("quiz"), quiz);
+    }
+    if let Some(exercise) = &step.exercise {
+        if step.quiz.is_some() {
+            linter.report(path("exercise"), "step cannot have both a quiz and an exercise");
+        }
+        lint_exercise(linter, &path("exercise"), exercise);
+    }
+}
+
+fn lint_exercise(linter: &mut Linter, path: &str, exercise: &Exercise) {
+    if exercise.instructions.trim().is_empty() {
+        linter.report(format!("{}.instructions", path), "must not be empty");
+    } else if exercise.instructions.chars().count() > MAX_PROMPT_LENGTH {
+        linter.report(format!("{}.instructions", path), format!("must be at most {} characters", MAX_PROMPT_LENGTH));
+    }
+    if exercise.rubric.is_empty() {
+        linter.report(format!("{}.rubric", path), "rubric must have at least one criterion");
+    }
+    if !(exercise.pass_score > 0.0 && exercise.pass_score <= 1.0) {
+        linter.report(format!("{}.pass_score", path), "must be greater than 0 and at most 1");
+    }
+
+    for (index, criterion) in exercise.rubric.iter().enumerate() {
+        let criterion_path = format!("{}.rubric[{}]", path, index);
+        if criterion.id.trim().is_empty() {
+            linter.report(format!("{}.id", criterion_path), "must not be empty");
+        } else if exercise.rubric[..index].iter().any(|other| other.id == criterion.id) {
+            linter.report(format!("{}.id", criterion_path), format!("duplicate criterion id '{}'", criterion.id));
+        }
+        if criterion.description.trim().is_empty() {
+            linter.report(format!("{}.description", criterion_path), "must not be empty");
+        }
+        if criterion.points == 0 {
+            linter.report(format!("{}.points", criterion_path), "must be at least 1");
+        }
+    }
+}
+
+fn lint_quiz(linter: &mut Linter, path: &str, quiz: &Quiz) {
+    if quiz.questions.is_empty() {
+        linter.report(format!("{}.questions", path), "quiz must have at least one question");
+    }
+    if !(quiz.pass_score > 0.0 && quiz.pass_score <= 1.0) {
+        linter.report(format!("{}.pass_score", path), "must be greater than 0 and at most 1");
+    }
+
+    for (index, question) in quiz.questions.iter().enumerate() {
+        let question_path = format!("{}.questions[{}]", path, index);
+        if question.id.trim().is_empty() {
+            linter.report(format!("{}.id", question_path), "must not be empty");
+        } else if quiz.questions[..index].iter().any(|other| other.id == question.id) {
+            linter.report(format!("{}.id", question_path), format!("duplicate question id '{}'", question.id));
+        }
+        if question.prompt.trim().is_empty() {
+            linter.report(format!("{}.prompt", question_path), "must not be empty");
+        }
+
+        match &question.kind {
+            QuizQuestionKind::MultipleChoice { options, correct_options } => {
+                if options.len() < 2 {
+                    linter.report(format!("{}.options", question_path), "must contain at least two options");
+                }
+                if correct_options.is_empty() {
+                    linter.report(format!("{}.correct_options", question_path), "must contain at least one option");
+                }
+                if let Some(out_of_range) = correct_options.iter().find(|&&option| option >= options.len()) {
+                    linter.report(format!("{}.correct_options", question_path), format!("option {} does not exist", out_of_range));
+                }
+            }
+            QuizQuestionKind::ShortAnswer { accepted_answers } => {
+                if accepted_answers.iter().all(|answer| answer.trim().is_empty()) {
+                    linter.report(format!("{}.accepted_answers", question_path), "must contain at least one answer");
+                }
+            }
+        }
+    }
+}
+
+/// Validates a whole catalogue of topics, including uniqueness of topic ids.
+///
+/// # Returns
+///
+/// A report for every topic that failed validation.
+pub fn validate_catalogue(topics: &[Topic]) -> Vec<ValidationReport> {
+    let known_topic_ids: Vec<String> = topics.iter().map(|topic| topic.id.clone()).collect();
+
+    topics.iter()
+        .enumerate()
+        .filter_map(|(index, topic)| {
+            let mut report = match validate_topic(topic, &known_topic_ids) {
+                Ok(()) => ValidationReport { topic_id: topic.id.clone(), issues: vec![] },
+                Err(report) => report,
+            };
+            if topics[..index].iter().any(|other| other.id == topic.id) {
+                report.issues.push(ValidationIssue {
+                    path: "id".to_string(),
+                    message: format!("duplicate topic id '{}'", topic.id),
+                });
+            }
+            if report.issues.is_empty() { None } else { Some(report) }
+        })
+        .collect()
+}
+
+/// Checks that a markdown document is well-formed.
+///
+/// This is not a full markdown parser; it catches the mistakes that break rendering
+/// in the frontend: unclosed code fences, unbalanced inline code and bold markers,
+/// headings without a space after the `#` markers, and malformed links.
+///
+/// # Returns
+///
+/// A description of every problem found, with its line number.
+pub fn markdown_problems(markdown: &str) -> Vec<String> {
+    let mut problems = vec![];
+    let mut fence_start: Option<usize> = None;
+
+    for (index, line) in markdown.lines().enumerate() {
+        let line_number = index + 1;
+        let trimmed = line.trim_start();
+
+        if trimmed.starts_with("```") {
+            fence_start = match fence_start {
+                Some(_) => None,
+                None => Some(line_number),
+            };
+            continue;
+        }
+        if fence_start.is_some() {
+            continue;
+        }
+
+        let hashes = trimmed.chars().take_while(|&c| c == '#').count();
+        if hashes > 0 && hashes <= 6 && trimmed.len() > hashes && !trimmed[hashes..].starts_with(' ') {
+            problems.push(format!("line {}: heading markers must be followed by a space", line_number));
+        }
+
+        // Inline code spans are ignored when checking the remaining markers
+        if line.matches('`').count() % 2 == 1 {
+            problems.push(format!("line {}: unclosed inline code", line_number));
+            continue;
+        }
+        let text: String = line.split('`').step_by(2).collect::<Vec<_>>().join("");
+
+        if text.matches("**").count() % 2 == 1 {
+            problems.push(format!("line {}: unclosed bold marker", line_number));
+        }
+        if text.matches('[').count() != text.matches(']').count() {
+            problems.push(format!("line {}: unbalanced square brackets", line_number));
+        }
+        for (position, _) in text.match_indices("](") {
+            if !text[position..].contains(')') {
+                problems.push(format!("line {}: unclosed link target", line_number));
+            } else if text[position + 2..].starts_with(')') {
+                problems.push(format!("line {}: empty link target", line_number));
+            }
+        }
+    }
+
+    if let Some(line_number) = fence_start {
+        problems.push(format!("line {}: unclosed code fence", line_number));
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::topics::{get_all_topics, get_github_setup_topic};
+
+    #[test]
+    fn test_builtin_catalogue_is_valid() {
+        let reports = validate_catalogue(&get_all_topics());
+        assert!(reports.is_empty(), "{}", reports.iter().map(|r| r.to_string()).collect::<String>());
+    }
+
+    #[test]
+    fn test_validate_topic_reports_precise_paths() {
+        let mut topic = get_github_setup_topic();
+        topic.id = "GitHub Setup".to_string();
+        topic.prerequisites = vec!["docker-basics".to_string()];
+        topic.tools = Some(vec!["search_docs".to_string(), "run_shell".to_string()]);
+        topic.steps[1].prompt = " ".to_string();
+        topic.steps[2].title = topic.steps[0].title.to_uppercase();
+        topic.steps[3].suggested_questions = vec![];
+
+        let report = validate_topic(&topic, &[topic.id.clone()]).unwrap_err();
+
+        assert_eq!(report.messages(), vec![
+            "id: must be non-empty and contain only lowercase letters, digits and dashes",
+            "prerequisites[0]: unknown topic 'docker-basics'",
+            "tools[1]: unknown tool 'run_shell'",
+            "steps[1].prompt: must not be empty",
+            "steps[2].title: duplicate step title 'INTRODUCTION TO GITHUB'",
+            "steps[3].suggested_questions: must contain between 1 and 5 questions, found 0",
+        ]);
+    }
+
+    #[test]
+    fn test_validate_topic_rejects_reserved_ids() {
+        let mut topic = get_github_setup_topic();
+        topic.id = "index".to_string();
+
+        let report = validate_topic(&topic, &[topic.id.clone()]).unwrap_err();
+        assert_eq!(report.messages(), vec!["id: 'index' is reserved"]);
+    }
+
+    #[test]
+    fn test_markdown_problems() {
+        assert!(markdown_problems(&get_github_setup_topic().initial_message).is_empty());
+        assert!(markdown_problems("Run `git status` to see **changes** in [the docs](https://git-scm.com).").is_empty());
+
+        let problems = markdown_problems("#Heading\n**bold\n[link](\n```\ncode");
+        assert_eq!(problems, vec![
+            "line 1: heading markers must be followed by a space",
+            "line 2: unclosed bold marker",
+            "line 3: unclosed link target",
+            "line 4: unclosed code fence",
+        ]);
+    }
+}
@@ -0,0 +1,250 @@
+//! This module contains the logic for versioning topic content and migrating
+//! learner progress between topic versions.
+//!
+//! Steps are identified by stable ids so that inserting, removing or reordering
+//! steps does not change which steps a learner has completed.
+
+use crate::types::{Progress, Topic};
+
+/// Assigns a stable id to every step that does not have one yet.
+///
+/// Ids are derived from the step title and de-duplicated against the ids
+/// already used in the topic.
+///
+/// # Arguments
+///
+/// * `topic` - The topic whose steps should be given ids
+pub fn assign_step_ids(topic: &mut Topic) {
+    let mut used: Vec<String> = topic.steps.iter()
+        .filter(|step| !step.id.is_empty())
+        .map(|step| step.id.clone())
+        .collect();
+
+    for step in topic.steps.iter_mut().filter(|step| step.id.is_empty()) {
+        let base = slugify(&step.title);
+        let mut id = base.clone();
+        let mut suffix = 2;
+        while used.contains(&id) {
+            id = format!("{}-{}", base, suffix);
+            suffix += 1;
+        }
+        used.push(id.clone());
+        step.id = id;
+    }
+}
+
+/// Converts a title into a lowercase, dash-separated slug.
+fn slugify(title: &str) -> String {
+    let slug = title.to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+
+    if slug.is_empty() { "step".to_string() } else { slug }
+}
+
+/// Returns the ids of the steps a learner has completed in the given topic version.
+///
+/// Progress recorded before step ids existed only carries indices, which are
+/// resolved against the version the progress is pinned to.
+///
+/// # Arguments
+///
+/// * `progress` - The learner's progress
+/// * `topic` - The topic version the progress is pinned to
+pub fn completed_step_ids(progress: &Progress, topic: &Topic) -> Vec<String> {
+    if !progress.completed_step_ids.is_empty() {
+        return progress.completed_step_ids.clone();
+    }
+
+    progress.completed_steps.iter()
+        .filter_map(|&index| topic.steps.get(index))
+        .map(|step| step.id.clone())
+        .collect()
+}
+
+/// Marks a step of the pinned topic version as completed and advances the learner past it.
+///
+/// # Arguments
+///
+/// * `progress` - The learner's progress
+/// * `topic` - The topic version the progress is pinned to
+/// * `step_index` - The index of the completed step in `topic`
+///
+/// # Returns
+///
+/// `false` if the step was already completed, `true` otherwise.
+pub fn mark_step_completed(progress: &mut Progress, topic: &Topic, step_index: usize) -> bool {
+    if progress.completed_steps.contains(&step_index) {
+        return false;
+    }
+
+    progress.completed_step_ids = completed_step_ids(progress, topic);
+    if let Some(step) = topic.steps.get(step_index) {
+        progress.completed_step_ids.push(step.id.clone());
+    }
+    progress.completed_steps.push(step_index);
+    progress.completed_steps.sort(); // Ensure the list is always sorted
+    progress.current_step = step_index + 1;
+    true
+}
+
+/// Migrates a learner's progress from one topic version to another.
+///
+/// Completed steps are carried over by id, dropping steps that no longer exist.
+/// The current step follows its id to its new position; if it was removed, the
+/// learner is placed on the first step they have not completed yet.
+///
+/// # Arguments
+///
+/// * `progress` - The learner's progress pinned to `from`
+/// * `from` - The topic version the progress is currently pinned to
+/// * `to` - The topic version to migrate to
+///
+/// # Returns
+///
+/// The progress pinned to `to`.
+pub fn migrate_progress(progress: &Progress, from: &Topic, to: &Topic) -> Progress {
+    let current_step_id = from.steps.get(progress.current_step).map(|step| step.id.as_str());
+    migrate_step_ids(progress, &completed_step_ids(progress, from), current_step_id, to)
+}
+
+/// Migrates a learner's progress to a topic version when the version it is
+/// pinned to is no longer archived.
+///
+/// Only the completed steps recorded by id can be carried over. The learner is
+/// placed on the first step they have not completed yet.
+///
+/// # Arguments
+///
+/// * `progress` - The learner's progress
+/// * `to` - The topic version to migrate to
+///
+/// # Returns
+///
+/// The progress pinned to `to`.
+pub fn migrate_unarchived_progress(progress: &Progress, to: &Topic) -> Progress {
+    migrate_step_ids(progress, &progress.completed_step_ids, None, to)
+}
+
+/// Pins progress to a topic version, given the ids of the completed steps and of the current step.
+fn migrate_step_ids(progress: &Progress, completed_ids: &[String], current_step_id: Option<&str>, to: &Topic) -> Progress {
+    let mut completed_steps: Vec<usize> = to.steps.iter()
+        .enumerate()
+        .filter(|(_, step)| completed_ids.contains(&step.id))
+        .map(|(index, _)| index)
+        .collect();
+    completed_steps.sort();
+
+    let completed_step_ids: Vec<String> = completed_steps.iter()
+        .map(|&index| to.steps[index].id.clone())
+        .collect();
+
+    let first_incomplete = (0..to.steps.len())
+        .find(|index| !completed_steps.contains(index))
+        .unwrap_or(to.steps.len());
+
+    let current_step = current_step_id
+        .and_then(|current| to.steps.iter().position(|step| step.id == current))
+        .unwrap_or(first_incomplete);
+
+    Progress {
+        topic_id: progress.topic_id.clone(),
+        topic_version: to.version,
+        completed_steps,
+        completed_step_ids,
+        current_step,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::topics::get_github_setup_topic;
+
+    #[test]
+    fn test_assign_step_ids() {
+        let mut topic = get_github_setup_topic();
+        topic.steps[0].id = String::new();
+        topic.steps[1].id = String::new();
+        topic.steps[1].title = "Introduction to GitHub!".to_string();
+
+        assign_step_ids(&mut topic);
+
+        assert_eq!(topic.steps[0].id, "introduction-to-github");
+        assert_eq!(topic.steps[1].id, "introduction-to-github-2");
+    }
+
+    #[test]
+    fn test_migrate_progress() {
+        let from = get_github_setup_topic();
+        let mut to = from.clone();
+        to.version = 2;
+        // Remove "Create a GitHub account" and insert a new step at the front
+        to.steps.remove(1);
+        let mut new_step = to.steps[0].clone();
+        new_step.id = "what-is-version-control".to_string();
+        to.steps.insert(0, new_step);
+
+        let progress = Progress {
+            topic_id: from.id.clone(),
+            topic_version: 1,
+            completed_steps: vec![0, 1, 2],
+            completed_step_ids: vec![],
+            current_step: 3,
+        };
+
+        let migrated = migrate_progress(&progress, &from, &to);
+
+        assert_eq!(migrated.topic_version, 2);
+        assert_eq!(migrated.completed_steps, vec![1, 2]);
+        assert_eq!(migrated.completed_step_ids, vec![from.steps[0].id.clone(), from.steps[2].id.clone()]);
+        assert_eq!(migrated.current_step, 3);
+        assert_eq!(to.steps[migrated.current_step].id, from.steps[3].id);
+    }
+
+    #[test]
+    fn test_migrate_progress_when_current_step_removed() {
+        let from = get_github_setup_topic();
+        let mut to = from.clone();
+        to.version = 2;
+        to.steps.remove(1);
+
+        let progress = Progress {
+            topic_id: from.id.clone(),
+            topic_version: 1,
+            completed_steps: vec![0],
+            completed_step_ids: vec![],
+            current_step: 1,
+        };
+
+        let migrated = migrate_progress(&progress, &from, &to);
+
+        assert_eq!(migrated.completed_steps, vec![0]);
+        assert_eq!(migrated.current_step, 1);
+        assert_eq!(to.steps[1].id, from.steps[2].id);
+    }
+
+    #[test]
+    fn test_migrate_unarchived_progress() {
+        let mut to = get_github_setup_topic();
+        to.version = 3;
+        to.steps.remove(0);
+
+        let progress = Progress {
+            topic_id: to.id.clone(),
+            topic_version: 1,
+            completed_steps: vec![0, 1],
+            completed_step_ids: vec!["introduction-to-github".to_string(), to.steps[0].id.clone()],
+            current_step: 2,
+        };
+
+        let migrated = migrate_unarchived_progress(&progress, &to);
+
+        assert_eq!(migrated.topic_version, 3);
+        assert_eq!(migrated.completed_steps, vec![0]);
+        assert_eq!(migrated.completed_step_ids, vec![to.steps[0].id.clone()]);
+        assert_eq!(migrated.current_step, 1);
+    }
+}